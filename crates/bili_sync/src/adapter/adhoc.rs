@@ -0,0 +1,109 @@
+use std::path::Path;
+use std::pin::Pin;
+
+use anyhow::{Context, Result};
+use bili_sync_entity::video_source::SourceType;
+use bili_sync_entity::*;
+use futures::{Stream, StreamExt};
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::{OnConflict, SimpleExpr};
+use sea_orm::ActiveValue::Set;
+use sea_orm::{DatabaseConnection, Unchanged};
+
+use crate::adapter::{VideoListModel, VideoListModelEnum, _ActiveModel};
+use crate::bilibili::{BiliClient, Video, VideoInfo};
+
+impl VideoListModel for adhoc::Model {
+    fn filter_expr(&self) -> SimpleExpr {
+        video_source::Column::SourceType
+            .eq(SourceType::Adhoc)
+            .and(video_source::Column::SourceId.eq(self.id))
+    }
+
+    fn set_relation_id(&self, source_model: &mut video_source::ActiveModel) {
+        source_model.source_type = Set(SourceType::Adhoc);
+        source_model.source_id = Set(self.id);
+    }
+
+    fn path(&self) -> &Path {
+        Path::new(self.path.as_str())
+    }
+
+    fn name(&self) -> String {
+        "单独下载".to_string()
+    }
+
+    fn get_latest_row_at(&self) -> DateTime {
+        // 单独下载的视频没有先后顺序，不能根据时间提前结束扫描，重复的视频会在写入数据库时被忽略
+        DateTime::default()
+    }
+
+    fn update_latest_row_at(&self, datetime: DateTime) -> _ActiveModel {
+        _ActiveModel::Adhoc(adhoc::ActiveModel {
+            id: Unchanged(self.id),
+            latest_row_at: Set(datetime),
+            ..Default::default()
+        })
+    }
+
+    fn log_refresh_video_start(&self) {
+        info!("开始获取单独下载的视频信息..");
+    }
+
+    fn log_refresh_video_end(&self, count: usize) {
+        info!("获取单独下载的视频信息完成，共 {} 条视频", count);
+    }
+
+    fn log_fetch_video_start(&self) {
+        info!("开始填充单独下载的视频详情..");
+    }
+
+    fn log_fetch_video_end(&self) {
+        info!("填充单独下载的视频详情完成");
+    }
+
+    fn log_download_video_start(&self) {
+        info!("开始下载单独下载的视频..");
+    }
+
+    fn log_download_video_end(&self) {
+        info!("下载单独下载的视频完成");
+    }
+}
+
+pub(super) async fn adhoc_from<'a>(
+    bvids: &'a [String],
+    path: &Path,
+    bili_client: &'a BiliClient,
+    connection: &DatabaseConnection,
+) -> Result<(
+    VideoListModelEnum,
+    Pin<Box<dyn Stream<Item = Result<VideoInfo>> + 'a + Send>>,
+)> {
+    adhoc::Entity::insert(adhoc::ActiveModel {
+        id: Set(1),
+        path: Set(path.to_string_lossy().to_string()),
+        ..Default::default()
+    })
+    .on_conflict(
+        OnConflict::column(adhoc::Column::Id)
+            .update_column(adhoc::Column::Path)
+            .to_owned(),
+    )
+    .exec(connection)
+    .await?;
+    let video_stream = futures::stream::iter(bvids).then(move |bvid| async move {
+        async { Video::new(bili_client, bvid.clone())?.get_view_info().await }
+            .await
+            .with_context(|| format!("failed to get view info of video {}", bvid))
+    });
+    Ok((
+        adhoc::Entity::find()
+            .filter(adhoc::Column::Id.eq(1))
+            .one(connection)
+            .await?
+            .context("adhoc not found")?
+            .into(),
+        Box::pin(video_stream),
+    ))
+}
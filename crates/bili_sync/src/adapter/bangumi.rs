@@ -0,0 +1,107 @@
+use std::path::Path;
+use std::pin::Pin;
+
+use anyhow::{Context, Result};
+use bili_sync_entity::video_source::SourceType;
+use bili_sync_entity::*;
+use futures::Stream;
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::{OnConflict, SimpleExpr};
+use sea_orm::ActiveValue::Set;
+use sea_orm::{DatabaseConnection, Unchanged};
+
+use crate::adapter::{VideoListModel, VideoListModelEnum, _ActiveModel};
+use crate::bilibili::{Bangumi, BangumiItem, BiliClient, VideoInfo};
+
+impl VideoListModel for bangumi::Model {
+    fn filter_expr(&self) -> SimpleExpr {
+        video_source::Column::SourceType
+            .eq(SourceType::Bangumi)
+            .and(video_source::Column::SourceId.eq(self.id))
+    }
+
+    fn set_relation_id(&self, source_model: &mut video_source::ActiveModel) {
+        source_model.source_type = Set(SourceType::Bangumi);
+        source_model.source_id = Set(self.id);
+    }
+
+    fn path(&self) -> &Path {
+        Path::new(self.path.as_str())
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_latest_row_at(&self) -> DateTime {
+        self.latest_row_at
+    }
+
+    fn update_latest_row_at(&self, datetime: DateTime) -> _ActiveModel {
+        _ActiveModel::Bangumi(bangumi::ActiveModel {
+            id: Unchanged(self.id),
+            latest_row_at: Set(datetime),
+            ..Default::default()
+        })
+    }
+
+    fn log_refresh_video_start(&self) {
+        info!("开始扫描番剧「{}」..", self.name);
+    }
+
+    fn log_refresh_video_end(&self, count: usize) {
+        info!("扫描番剧「{}」完成，获取到 {} 条新剧集", self.name, count);
+    }
+
+    fn log_fetch_video_start(&self) {
+        info!("开始填充番剧「{}」剧集详情..", self.name);
+    }
+
+    fn log_fetch_video_end(&self) {
+        info!("填充番剧「{}」剧集详情完成", self.name);
+    }
+
+    fn log_download_video_start(&self) {
+        info!("开始下载番剧「{}」剧集..", self.name);
+    }
+
+    fn log_download_video_end(&self) {
+        info!("下载番剧「{}」剧集完成", self.name);
+    }
+}
+
+pub(super) async fn bangumi_from<'a>(
+    bangumi_item: &'a BangumiItem,
+    path: &Path,
+    bili_client: &'a BiliClient,
+    connection: &DatabaseConnection,
+) -> Result<(
+    VideoListModelEnum,
+    Pin<Box<dyn Stream<Item = Result<VideoInfo>> + 'a + Send>>,
+)> {
+    let bangumi = Bangumi::new(bili_client, bangumi_item);
+    let season = bangumi.get_season_detail().await?;
+    bangumi::Entity::insert(bangumi::ActiveModel {
+        season_id: Set(season.season_id),
+        media_id: Set(season.media_id),
+        name: Set(season.title.clone()),
+        path: Set(path.to_string_lossy().to_string()),
+        ..Default::default()
+    })
+    .on_conflict(
+        OnConflict::column(bangumi::Column::SeasonId)
+            .update_columns([bangumi::Column::Name, bangumi::Column::Path])
+            .to_owned(),
+    )
+    .exec(connection)
+    .await?;
+    Ok((
+        bangumi::Entity::find()
+            .filter(bangumi::Column::SeasonId.eq(season.season_id))
+            .one(connection)
+            .await?
+            .context("bangumi not found")?
+            .into(),
+        Box::pin(bangumi.into_video_stream(season)),
+    ))
+}
@@ -2,6 +2,7 @@ use std::path::Path;
 use std::pin::Pin;
 
 use anyhow::{Context, Result};
+use bili_sync_entity::video_source::SourceType;
 use bili_sync_entity::*;
 use futures::Stream;
 use sea_orm::entity::prelude::*;
@@ -14,17 +15,24 @@ use crate::bilibili::{BiliClient, FavoriteList, VideoInfo};
 
 impl VideoListModel for favorite::Model {
     fn filter_expr(&self) -> SimpleExpr {
-        video::Column::FavoriteId.eq(self.id)
+        video_source::Column::SourceType
+            .eq(SourceType::Favorite)
+            .and(video_source::Column::SourceId.eq(self.id))
     }
 
-    fn set_relation_id(&self, video_model: &mut video::ActiveModel) {
-        video_model.favorite_id = Set(Some(self.id));
+    fn set_relation_id(&self, source_model: &mut video_source::ActiveModel) {
+        source_model.source_type = Set(SourceType::Favorite);
+        source_model.source_id = Set(self.id);
     }
 
     fn path(&self) -> &Path {
         Path::new(self.path.as_str())
     }
 
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
     fn get_latest_row_at(&self) -> DateTime {
         self.latest_row_at
     }
@@ -1,3 +1,5 @@
+mod adhoc;
+mod bangumi;
 mod collection;
 mod favorite;
 mod submission;
@@ -14,16 +16,20 @@ use sea_orm::sea_query::SimpleExpr;
 use sea_orm::DatabaseConnection;
 
 #[rustfmt::skip]
+use bili_sync_entity::adhoc::Model as Adhoc;
+use bili_sync_entity::bangumi::Model as Bangumi;
 use bili_sync_entity::collection::Model as Collection;
 use bili_sync_entity::favorite::Model as Favorite;
 use bili_sync_entity::submission::Model as Submission;
 use bili_sync_entity::watch_later::Model as WatchLater;
 
+use crate::adapter::adhoc::adhoc_from;
+use crate::adapter::bangumi::bangumi_from;
 use crate::adapter::collection::collection_from;
 use crate::adapter::favorite::favorite_from;
 use crate::adapter::submission::submission_from;
 use crate::adapter::watch_later::watch_later_from;
-use crate::bilibili::{BiliClient, CollectionItem, VideoInfo};
+use crate::bilibili::{BangumiItem, BiliClient, CollectionItem, Resource, VideoInfo};
 
 #[enum_dispatch]
 pub enum VideoListModelEnum {
@@ -31,6 +37,8 @@ pub enum VideoListModelEnum {
     Collection,
     Submission,
     WatchLater,
+    Bangumi,
+    Adhoc,
 }
 
 #[enum_dispatch(VideoListModelEnum)]
@@ -38,12 +46,15 @@ pub trait VideoListModel {
     /// 获取特定视频列表的筛选条件
     fn filter_expr(&self) -> SimpleExpr;
 
-    // 为 video_model 设置该视频列表的关联 id
-    fn set_relation_id(&self, video_model: &mut bili_sync_entity::video::ActiveModel);
+    // 为视频与该视频列表的关联设置视频列表的类型与 id
+    fn set_relation_id(&self, source_model: &mut bili_sync_entity::video_source::ActiveModel);
 
     // 获取视频列表的保存路径
     fn path(&self) -> &Path;
 
+    // 获取视频列表的名称，作为音频文件的专辑名
+    fn name(&self) -> String;
+
     /// 获取视频 model 中记录的最新时间
     fn get_latest_row_at(&self) -> DateTime;
 
@@ -77,6 +88,20 @@ pub enum Args<'a> {
     Collection { collection_item: &'a CollectionItem },
     WatchLater,
     Submission { upper_id: &'a str },
+    Bangumi { bangumi_item: &'a BangumiItem },
+    Adhoc { bvids: &'a [String] },
+}
+
+impl<'a> From<&'a Resource> for Args<'a> {
+    fn from(resource: &'a Resource) -> Self {
+        match resource {
+            Resource::Favorite(fid) => Args::Favorite { fid },
+            Resource::Collection(collection_item) => Args::Collection { collection_item },
+            Resource::Submission(upper_id) => Args::Submission { upper_id },
+            Resource::WatchLater => Args::WatchLater,
+            Resource::Bangumi(bangumi_item) => Args::Bangumi { bangumi_item },
+        }
+    }
 }
 
 pub async fn video_list_from<'a>(
@@ -93,6 +118,8 @@ pub async fn video_list_from<'a>(
         Args::Collection { collection_item } => collection_from(collection_item, path, bili_client, connection).await,
         Args::WatchLater => watch_later_from(path, bili_client, connection).await,
         Args::Submission { upper_id } => submission_from(upper_id, path, bili_client, connection).await,
+        Args::Bangumi { bangumi_item } => bangumi_from(bangumi_item, path, bili_client, connection).await,
+        Args::Adhoc { bvids } => adhoc_from(bvids, path, bili_client, connection).await,
     }
 }
 
@@ -101,6 +128,8 @@ pub enum _ActiveModel {
     Collection(bili_sync_entity::collection::ActiveModel),
     Submission(bili_sync_entity::submission::ActiveModel),
     WatchLater(bili_sync_entity::watch_later::ActiveModel),
+    Bangumi(bili_sync_entity::bangumi::ActiveModel),
+    Adhoc(bili_sync_entity::adhoc::ActiveModel),
 }
 
 impl _ActiveModel {
@@ -118,6 +147,12 @@ impl _ActiveModel {
             _ActiveModel::WatchLater(model) => {
                 model.save(connection).await?;
             }
+            _ActiveModel::Bangumi(model) => {
+                model.save(connection).await?;
+            }
+            _ActiveModel::Adhoc(model) => {
+                model.save(connection).await?;
+            }
         }
         Ok(())
     }
@@ -7,7 +7,7 @@ pub struct PageAnalyzer {
     info: serde_json::Value,
 }
 
-#[derive(Debug, strum::FromRepr, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, strum::FromRepr, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum VideoQuality {
     Quality360p = 16,
     Quality480p = 32,
@@ -53,7 +53,9 @@ impl AudioQuality {
 }
 
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Debug, strum::EnumString, strum::Display, strum::AsRefStr, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[derive(
+    Debug, Clone, strum::EnumString, strum::Display, strum::AsRefStr, PartialEq, PartialOrd, Serialize, Deserialize,
+)]
 pub enum VideoCodecs {
     #[strum(serialize = "hev")]
     HEV,
@@ -64,7 +66,8 @@ pub enum VideoCodecs {
 }
 
 // 视频流的筛选偏好
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct FilterOption {
     pub video_max_quality: VideoQuality,
     pub video_min_quality: VideoQuality,
@@ -94,31 +97,107 @@ impl Default for FilterOption {
 }
 
 // 上游项目中的五种流类型，不过目测应该只有 Flv、DashVideo、DashAudio 三种会被用到
+// 每种流都包含主链接与若干备用链接，主链接位于最前
 #[derive(Debug, PartialEq, PartialOrd)]
 pub enum Stream {
-    Flv(String),
-    Html5Mp4(String),
-    EpisodeTryMp4(String),
+    Flv(Vec<String>),
+    Html5Mp4(Vec<String>),
+    EpisodeTryMp4(Vec<String>),
     DashVideo {
-        url: String,
+        urls: Vec<String>,
         quality: VideoQuality,
         codecs: VideoCodecs,
     },
     DashAudio {
-        url: String,
+        urls: Vec<String>,
         quality: AudioQuality,
     },
 }
 
 // 通用的获取流链接的方法，交由 Downloader 使用
 impl Stream {
-    pub fn url(&self) -> &str {
+    pub fn urls(&self) -> &[String] {
         match self {
-            Self::Flv(url) => url,
-            Self::Html5Mp4(url) => url,
-            Self::EpisodeTryMp4(url) => url,
-            Self::DashVideo { url, .. } => url,
-            Self::DashAudio { url, .. } => url,
+            Self::Flv(urls) => urls,
+            Self::Html5Mp4(urls) => urls,
+            Self::EpisodeTryMp4(urls) => urls,
+            Self::DashVideo { urls, .. } => urls,
+            Self::DashAudio { urls, .. } => urls,
+        }
+    }
+}
+
+/// 获取 durl 或 dash 中单个流的所有链接，两种接口中的字段名称不同
+fn stream_urls(stream: &serde_json::Value) -> Option<Vec<String>> {
+    let url = ["url", "baseUrl", "base_url"]
+        .into_iter()
+        .find_map(|key| stream[key].as_str())?;
+    let backup_urls = ["backup_url", "backupUrl"]
+        .into_iter()
+        .find_map(|key| stream[key].as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|url| url.as_str());
+    let mut urls = vec![url.to_owned()];
+    for backup_url in backup_urls {
+        if !urls.iter().any(|url| url == backup_url) {
+            urls.push(backup_url.to_owned());
+        }
+    }
+    Some(urls)
+}
+
+/// 下载的视频流的画质、编码与音质，混合流无法得知这些信息，均为 None
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamQuality {
+    pub video_quality: Option<VideoQuality>,
+    pub video_codec: Option<VideoCodecs>,
+    pub audio_quality: Option<AudioQuality>,
+}
+
+impl StreamQuality {
+    /// 是否严格优于另一个视频流，依次比较画质、编码在筛选偏好中的顺序与音质，与 best_stream 的选择规则一致
+    pub fn is_better_than(&self, other: &StreamQuality, filter_option: &FilterOption) -> bool {
+        let key = |quality: &StreamQuality| {
+            // 越靠前的编码越优先，不在偏好中的编码最差
+            let codec_rank = quality.video_codec.as_ref().map_or(0, |codec| {
+                filter_option
+                    .codecs
+                    .iter()
+                    .position(|c| c == codec)
+                    .map_or(0, |position| filter_option.codecs.len() - position)
+            });
+            (quality.video_quality, codec_rank, quality.audio_quality)
+        };
+        key(self) > key(other)
+    }
+}
+
+impl Stream {
+    pub fn quality(&self) -> StreamQuality {
+        match self {
+            Self::DashVideo { quality, codecs, .. } => StreamQuality {
+                video_quality: Some(*quality),
+                video_codec: Some(codecs.clone()),
+                audio_quality: None,
+            },
+            Self::DashAudio { quality, .. } => StreamQuality {
+                audio_quality: Some(*quality),
+                ..Default::default()
+            },
+            _ => StreamQuality::default(),
+        }
+    }
+}
+
+impl BestStream {
+    pub fn quality(&self) -> StreamQuality {
+        match self {
+            Self::Mixed(stream) => stream.quality(),
+            Self::VideoAudio { video, audio } => StreamQuality {
+                audio_quality: audio.as_ref().and_then(|audio| audio.quality().audio_quality),
+                ..video.quality()
+            },
         }
     }
 }
@@ -157,26 +236,17 @@ impl PageAnalyzer {
     fn streams(&mut self, filter_option: &FilterOption) -> Result<Vec<Stream>> {
         if self.is_flv_stream() {
             return Ok(vec![Stream::Flv(
-                self.info["durl"][0]["url"]
-                    .as_str()
-                    .context("invalid flv stream")?
-                    .to_string(),
+                stream_urls(&self.info["durl"][0]).context("invalid flv stream")?,
             )]);
         }
         if self.is_html5_mp4_stream() {
             return Ok(vec![Stream::Html5Mp4(
-                self.info["durl"][0]["url"]
-                    .as_str()
-                    .context("invalid html5 mp4 stream")?
-                    .to_string(),
+                stream_urls(&self.info["durl"][0]).context("invalid html5 mp4 stream")?,
             )]);
         }
         if self.is_episode_try_mp4_stream() {
             return Ok(vec![Stream::EpisodeTryMp4(
-                self.info["durl"][0]["url"]
-                    .as_str()
-                    .context("invalid episode try mp4 stream")?
-                    .to_string(),
+                stream_urls(&self.info["durl"][0]).context("invalid episode try mp4 stream")?,
             )]);
         }
         let mut streams: Vec<Stream> = Vec::new();
@@ -185,11 +255,9 @@ impl PageAnalyzer {
             .ok_or(BiliError::RiskControlOccurred)?
             .iter()
         {
-            let (Some(url), Some(quality), Some(codecs)) = (
-                video["baseUrl"].as_str(),
-                video["id"].as_u64(),
-                video["codecs"].as_str(),
-            ) else {
+            let (Some(urls), Some(quality), Some(codecs)) =
+                (stream_urls(video), video["id"].as_u64(), video["codecs"].as_str())
+            else {
                 continue;
             };
             let quality = VideoQuality::from_repr(quality as usize).context("invalid video stream quality")?;
@@ -209,56 +277,61 @@ impl PageAnalyzer {
             {
                 continue;
             }
-            streams.push(Stream::DashVideo {
-                url: url.to_string(),
-                quality,
-                codecs,
-            });
+            streams.push(Stream::DashVideo { urls, quality, codecs });
         }
         if let Some(audios) = self.info["dash"]["audio"].as_array() {
             for audio in audios.iter() {
-                let (Some(url), Some(quality)) = (audio["baseUrl"].as_str(), audio["id"].as_u64()) else {
+                let (Some(urls), Some(quality)) = (stream_urls(audio), audio["id"].as_u64()) else {
                     continue;
                 };
                 let quality = AudioQuality::from_repr(quality as usize).context("invalid audio stream quality")?;
                 if quality < filter_option.audio_min_quality || quality > filter_option.audio_max_quality {
                     continue;
                 }
-                streams.push(Stream::DashAudio {
-                    url: url.to_string(),
-                    quality,
-                });
+                streams.push(Stream::DashAudio { urls, quality });
             }
         }
         let flac = &self.info["dash"]["flac"]["audio"];
         if !(filter_option.no_hires || flac.is_null()) {
-            let (Some(url), Some(quality)) = (flac["baseUrl"].as_str(), flac["id"].as_u64()) else {
+            let (Some(urls), Some(quality)) = (stream_urls(flac), flac["id"].as_u64()) else {
                 bail!("invalid flac stream");
             };
             let quality = AudioQuality::from_repr(quality as usize).context("invalid flac stream quality")?;
             if quality >= filter_option.audio_min_quality && quality <= filter_option.audio_max_quality {
-                streams.push(Stream::DashAudio {
-                    url: url.to_string(),
-                    quality,
-                });
+                streams.push(Stream::DashAudio { urls, quality });
             }
         }
         let dolby_audio = &self.info["dash"]["dolby"]["audio"][0];
         if !(filter_option.no_dolby_audio || dolby_audio.is_null()) {
-            let (Some(url), Some(quality)) = (dolby_audio["baseUrl"].as_str(), dolby_audio["id"].as_u64()) else {
+            let (Some(urls), Some(quality)) = (stream_urls(dolby_audio), dolby_audio["id"].as_u64()) else {
                 bail!("invalid dolby audio stream");
             };
             let quality = AudioQuality::from_repr(quality as usize).context("invalid dolby audio stream quality")?;
             if quality >= filter_option.audio_min_quality && quality <= filter_option.audio_max_quality {
-                streams.push(Stream::DashAudio {
-                    url: url.to_string(),
-                    quality,
-                });
+                streams.push(Stream::DashAudio { urls, quality });
             }
         }
         Ok(streams)
     }
 
+    /// 仅获取最佳的音频流，用于只下载音频的视频来源
+    /// 对于只提供混合流的视频，返回该混合流，需要调用方从中提取音频
+    pub fn best_audio_stream(&mut self, filter_option: &FilterOption) -> Result<Stream> {
+        let streams = self.streams(filter_option)?;
+        if self.is_flv_stream() || self.is_html5_mp4_stream() || self.is_episode_try_mp4_stream() {
+            return streams.into_iter().next().context("no stream found");
+        }
+        streams
+            .into_iter()
+            .filter_map(|s| match s {
+                Stream::DashAudio { quality, .. } => Some((quality, s)),
+                _ => None,
+            })
+            .max_by_key(|(quality, _)| *quality)
+            .map(|(_, s)| s)
+            .context("no audio stream found")
+    }
+
     pub fn best_stream(&mut self, filter_option: &FilterOption) -> Result<BestStream> {
         let streams = self.streams(filter_option)?;
         if self.is_flv_stream() || self.is_html5_mp4_stream() || self.is_episode_try_mp4_stream() {
@@ -336,6 +409,115 @@ mod tests {
         .is_sorted());
     }
 
+    #[test]
+    fn test_backup_urls() {
+        let mut analyzer = PageAnalyzer::new(serde_json::json!({
+            "dash": {
+                "video": [{
+                    "id": 80,
+                    "codecs": "avc1.640032",
+                    "baseUrl": "https://upos-sz-mirrorcos.bilivideo.com/video.m4s",
+                    "backupUrl": [
+                        "https://upos-sz-mirrorali.bilivideo.com/video.m4s",
+                        "https://upos-sz-mirrorcos.bilivideo.com/video.m4s"
+                    ]
+                }],
+                "audio": [{
+                    "id": 30280,
+                    "base_url": "https://upos-sz-mirrorcos.bilivideo.com/audio.m4s"
+                }]
+            }
+        }));
+        let BestStream::VideoAudio {
+            video,
+            audio: Some(audio),
+        } = analyzer.best_stream(&FilterOption::default()).unwrap()
+        else {
+            panic!("unexpected stream type");
+        };
+        assert_eq!(
+            video.urls(),
+            [
+                "https://upos-sz-mirrorcos.bilivideo.com/video.m4s",
+                "https://upos-sz-mirrorali.bilivideo.com/video.m4s"
+            ]
+        );
+        assert_eq!(audio.urls(), ["https://upos-sz-mirrorcos.bilivideo.com/audio.m4s"]);
+    }
+
+    #[test]
+    fn test_stream_quality() {
+        let filter_option = FilterOption::default();
+        let quality = |video_quality, video_codec, audio_quality| StreamQuality {
+            video_quality: Some(video_quality),
+            video_codec: Some(video_codec),
+            audio_quality,
+        };
+        let current = quality(
+            VideoQuality::Quality1080p,
+            VideoCodecs::AVC,
+            Some(AudioQuality::Quality192k),
+        );
+        assert!(quality(VideoQuality::Quality4k, VideoCodecs::AVC, None).is_better_than(&current, &filter_option));
+        // 默认的编码偏好中 HEVC 优于 AVC
+        assert!(quality(
+            VideoQuality::Quality1080p,
+            VideoCodecs::HEV,
+            Some(AudioQuality::Quality192k)
+        )
+        .is_better_than(&current, &filter_option));
+        assert!(quality(
+            VideoQuality::Quality1080p,
+            VideoCodecs::AVC,
+            Some(AudioQuality::QualityHiRES)
+        )
+        .is_better_than(&current, &filter_option));
+        assert!(!current.is_better_than(&current, &filter_option));
+        assert!(!quality(
+            VideoQuality::Quality720p,
+            VideoCodecs::AV1,
+            Some(AudioQuality::QualityHiRES)
+        )
+        .is_better_than(&current, &filter_option));
+    }
+
+    #[test]
+    fn test_best_audio_stream() {
+        let info = serde_json::json!({
+            "dash": {
+                "video": [{ "id": 80, "codecs": "avc1.640032", "baseUrl": "https://example.com/video.m4s" }],
+                "audio": [
+                    { "id": 30280, "baseUrl": "https://example.com/192k.m4s" },
+                    { "id": 30216, "baseUrl": "https://example.com/64k.m4s" }
+                ],
+                "flac": { "audio": { "id": 30251, "baseUrl": "https://example.com/flac.m4s" } }
+            }
+        });
+        let best = |filter_option: &FilterOption| {
+            PageAnalyzer::new(info.clone())
+                .best_audio_stream(filter_option)
+                .unwrap()
+        };
+        assert!(matches!(
+            best(&FilterOption::default()),
+            Stream::DashAudio {
+                quality: AudioQuality::QualityHiRES,
+                ..
+            }
+        ));
+        let filter_option = FilterOption {
+            no_hires: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            best(&filter_option),
+            Stream::DashAudio {
+                quality: AudioQuality::Quality192k,
+                ..
+            }
+        ));
+    }
+
     #[ignore = "only for manual test"]
     #[tokio::test]
     async fn test_best_stream() {
@@ -357,7 +539,7 @@ mod tests {
         ];
         for (bvid, video_quality, audio_quality) in testcases.into_iter() {
             let client = BiliClient::new();
-            let video = Video::new(&client, bvid.to_owned());
+            let video = Video::new(&client, bvid.to_owned()).expect("invalid bvid");
             let pages = video.get_pages().await.expect("failed to get pages");
             let first_page = pages.into_iter().next().expect("no page found");
             let best_stream = video
@@ -0,0 +1,284 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_stream::try_stream;
+use chrono::serde::ts_seconds;
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use reqwest::Method;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::bilibili::favorite_list::Upper;
+use crate::bilibili::{BiliClient, Dimension, PageInfo, Validate, VideoInfo};
+
+/// 番剧可以通过 season_id、ep_id 或 media_id 中的任意一个指定，在配置文件中分别写作 ss123、ep123、md123
+#[derive(PartialEq, Eq, Hash, Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum BangumiItem {
+    Season(String),
+    Episode(String),
+    Media(String),
+}
+
+impl FromStr for BangumiItem {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (prefix, id) = s.split_at_checked(2).context("invalid bangumi key")?;
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+            bail!(
+                "invalid bangumi key {}, should be ss<season_id>, ep<ep_id> or md<media_id>",
+                s
+            );
+        }
+        let id = id.to_owned();
+        Ok(match prefix {
+            "ss" => BangumiItem::Season(id),
+            "ep" => BangumiItem::Episode(id),
+            "md" => BangumiItem::Media(id),
+            _ => bail!(
+                "invalid bangumi key {}, should be ss<season_id>, ep<ep_id> or md<media_id>",
+                s
+            ),
+        })
+    }
+}
+
+impl TryFrom<String> for BangumiItem {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl Display for BangumiItem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BangumiItem::Season(id) => write!(f, "ss{}", id),
+            BangumiItem::Episode(id) => write!(f, "ep{}", id),
+            BangumiItem::Media(id) => write!(f, "md{}", id),
+        }
+    }
+}
+
+impl From<BangumiItem> for String {
+    fn from(value: BangumiItem) -> Self {
+        value.to_string()
+    }
+}
+
+pub struct Bangumi<'a> {
+    client: &'a BiliClient,
+    bangumi: &'a BangumiItem,
+}
+
+/// 剧集的详细信息，包含所有的集数，可以在获取多集的详情时复用
+#[derive(Debug, Deserialize)]
+pub struct Season {
+    pub season_id: i64,
+    pub media_id: i64,
+    pub title: String,
+    cover: String,
+    evaluate: String,
+    #[serde(default)]
+    styles: Vec<String>,
+    up_info: Option<Upper<i64>>,
+    episodes: Vec<Episode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Episode {
+    #[serde(rename = "id")]
+    ep_id: i64,
+    bvid: String,
+    cid: i64,
+    cover: String,
+    /// 单位为毫秒
+    duration: u64,
+    title: String,
+    long_title: String,
+    #[serde(with = "ts_seconds")]
+    pub_time: DateTime<Utc>,
+    dimension: Option<Dimension>,
+}
+
+impl Episode {
+    fn name(&self) -> String {
+        if self.long_title.is_empty() {
+            self.title.clone()
+        } else {
+            self.long_title.clone()
+        }
+    }
+}
+
+impl Season {
+    pub fn contains(&self, ep_id: i64) -> bool {
+        self.episodes.iter().any(|e| e.ep_id == ep_id)
+    }
+
+    /// 获取某一集的详细信息
+    /// 返回值与普通视频保持一致，包含剧集的风格标签与视频详情，其中视频详情仅有一个分页，分页号为该集在剧集中的序号
+    pub fn episode_detail(&self, ep_id: i64) -> Result<(Vec<String>, VideoInfo)> {
+        let (idx, episode) = self
+            .episodes
+            .iter()
+            .enumerate()
+            .find(|(_, e)| e.ep_id == ep_id)
+            .with_context(|| format!("episode {} not found in season {}", ep_id, self.title))?;
+        let upper = self.up_info.clone().unwrap_or(Upper {
+            mid: 0,
+            name: String::new(),
+            face: String::new(),
+        });
+        let page = PageInfo {
+            cid: episode.cid,
+            page: idx as i32 + 1,
+            name: episode.name(),
+            duration: (episode.duration / 1000) as u32,
+            first_frame: Some(episode.cover.clone()),
+            dimension: episode.dimension.clone(),
+            ..Default::default()
+        };
+        Ok((
+            self.styles.clone(),
+            VideoInfo::Detail {
+                title: self.title.clone(),
+                bvid: episode.bvid.clone(),
+                intro: self.evaluate.clone(),
+                cover: self.cover.clone(),
+                upper,
+                ctime: episode.pub_time,
+                pubtime: episode.pub_time,
+                pages: vec![page],
+                state: 0,
+            },
+        ))
+    }
+}
+
+impl<'a> Bangumi<'a> {
+    pub fn new(client: &'a BiliClient, bangumi: &'a BangumiItem) -> Self {
+        Self { client, bangumi }
+    }
+
+    /// media_id 无法直接查询剧集信息，需要先转换为 season_id
+    async fn get_season_id(&self, media_id: &str) -> Result<String> {
+        let res = self
+            .client
+            .request(Method::GET, "https://api.bilibili.com/pgc/review/user")
+            .await
+            .query(&[("media_id", media_id)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?
+            .validate()?;
+        Ok(res["result"]["media"]["season_id"]
+            .as_i64()
+            .context("season_id not found")?
+            .to_string())
+    }
+
+    async fn get_season(&self) -> Result<Value> {
+        let query = match self.bangumi {
+            BangumiItem::Season(season_id) => ("season_id", season_id.clone()),
+            BangumiItem::Episode(ep_id) => ("ep_id", ep_id.clone()),
+            BangumiItem::Media(media_id) => ("season_id", self.get_season_id(media_id).await?),
+        };
+        self.client
+            .request(Method::GET, "https://api.bilibili.com/pgc/view/web/season")
+            .await
+            .query(&[query])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?
+            .validate()
+    }
+
+    /// 获取剧集的详细信息，通过 ep_id 构造时获取该集所在的剧集
+    pub async fn get_season_detail(&self) -> Result<Season> {
+        let mut res = self.get_season().await?;
+        Ok(serde_json::from_value(res["result"].take())?)
+    }
+
+    /// 番剧的所有剧集都在一次请求中返回，接口按照集数正序排列，此处倒序输出以保证从新到旧
+    /// 剧集信息由调用方通过 get_season_detail 获取，与更新番剧记录共用同一次请求
+    pub fn into_video_stream(self, season: Season) -> impl Stream<Item = Result<VideoInfo>> + 'a {
+        try_stream! {
+            if season.episodes.is_empty() {
+                Err(anyhow!("no episodes found in bangumi {}", self.bangumi))?;
+            }
+            for episode in season.episodes.into_iter().rev() {
+                yield VideoInfo::Bangumi {
+                    title: season.title.clone(),
+                    bvid: episode.bvid,
+                    ep_id: episode.ep_id,
+                    cover: episode.cover,
+                    pubtime: episode.pub_time,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bangumi_item_parse() {
+        assert_eq!(
+            "ss12345".parse::<BangumiItem>().unwrap(),
+            BangumiItem::Season("12345".to_owned())
+        );
+        assert_eq!(
+            "ep67890".parse::<BangumiItem>().unwrap(),
+            BangumiItem::Episode("67890".to_owned())
+        );
+        assert_eq!(
+            "md28229233".parse::<BangumiItem>().unwrap(),
+            BangumiItem::Media("28229233".to_owned())
+        );
+        for invalid in ["", "ss", "12345", "av12345", "ssabc", "ep12a"] {
+            assert!(invalid.parse::<BangumiItem>().is_err(), "{} should be invalid", invalid);
+        }
+        assert_eq!(BangumiItem::Media("1".to_owned()).to_string(), "md1");
+    }
+
+    #[test]
+    fn test_season_episode_detail() {
+        let season: Season = serde_json::from_value(serde_json::json!({
+            "season_id": 1,
+            "media_id": 2,
+            "title": "番剧",
+            "cover": "https://example.com/cover.jpg",
+            "evaluate": "简介",
+            "styles": ["日常"],
+            "episodes": [
+                { "id": 101, "bvid": "BV1", "cid": 11, "cover": "c1", "duration": 1440000, "title": "1", "long_title": "第一集", "pub_time": 1700000000 },
+                { "id": 102, "bvid": "BV2", "cid": 12, "cover": "c2", "duration": 1440000, "title": "2", "long_title": "", "pub_time": 1700604800 }
+            ]
+        }))
+        .unwrap();
+        assert!(season.contains(102));
+        assert!(!season.contains(103));
+        // 同一剧集的多集可以复用一次请求的结果
+        let (styles, VideoInfo::Detail { bvid, pages, .. }) = season.episode_detail(102).unwrap() else {
+            unreachable!()
+        };
+        assert_eq!(styles, vec!["日常".to_owned()]);
+        assert_eq!(bvid, "BV2");
+        assert_eq!(
+            (pages[0].page, pages[0].name.as_str(), pages[0].duration),
+            (2, "2", 1440)
+        );
+        assert!(season.episode_detail(103).is_err());
+    }
+}
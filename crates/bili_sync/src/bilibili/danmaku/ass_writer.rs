@@ -184,7 +184,7 @@ impl<W: AsyncWrite> AssWriter<W> {
     }
 }
 
-fn escape_text(text: &str) -> Cow<str> {
+fn escape_text(text: &str) -> Cow<'_, str> {
     let text = text.trim();
     if memchr::memchr(b'\n', text.as_bytes()).is_some() {
         Cow::from(text.replace('\n', "\\N"))
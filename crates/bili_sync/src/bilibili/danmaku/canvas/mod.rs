@@ -10,8 +10,11 @@ use crate::bilibili::danmaku::danmu::DanmuType;
 use crate::bilibili::danmaku::{Danmu, DrawEffect, Drawable};
 use crate::bilibili::PageInfo;
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
 pub struct DanmakuOption {
+    /// 是否下载弹幕
+    pub enabled: bool,
     pub duration: f64,
     pub font: String,
     pub font_size: u32,
@@ -37,6 +40,7 @@ pub struct DanmakuOption {
 impl Default for DanmakuOption {
     fn default() -> Self {
         Self {
+            enabled: true,
             duration: 15.0,
             font: "黑体".to_string(),
             font_size: 25,
@@ -57,15 +61,15 @@ impl Default for DanmakuOption {
 pub struct CanvasConfig {
     pub width: u64,
     pub height: u64,
-    pub danmaku_option: &'static DanmakuOption,
+    pub danmaku_option: DanmakuOption,
 }
 impl CanvasConfig {
-    pub fn new(danmaku_option: &'static DanmakuOption, page: &PageInfo) -> Self {
+    pub fn new(danmaku_option: &DanmakuOption, page: &PageInfo) -> Self {
         let (width, height) = Self::dimension(page);
         Self {
             width,
             height,
-            danmaku_option,
+            danmaku_option: danmaku_option.clone(),
         }
     }
 
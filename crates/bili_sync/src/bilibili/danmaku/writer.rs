@@ -4,9 +4,8 @@ use anyhow::Result;
 use tokio::fs::{self, File};
 
 use crate::bilibili::danmaku::canvas::CanvasConfig;
-use crate::bilibili::danmaku::{AssWriter, Danmu};
+use crate::bilibili::danmaku::{AssWriter, DanmakuOption, Danmu};
 use crate::bilibili::PageInfo;
-use crate::config::CONFIG;
 
 pub struct DanmakuWriter<'a> {
     page: &'a PageInfo,
@@ -18,11 +17,11 @@ impl<'a> DanmakuWriter<'a> {
         DanmakuWriter { page, danmaku }
     }
 
-    pub async fn write(self, path: PathBuf) -> Result<()> {
+    pub async fn write(self, path: PathBuf, danmaku_option: &DanmakuOption) -> Result<()> {
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent).await?;
         }
-        let canvas_config = CanvasConfig::new(&CONFIG.danmaku_option, self.page);
+        let canvas_config = CanvasConfig::new(danmaku_option, self.page);
         let mut writer =
             AssWriter::construct(File::create(path).await?, self.page.name.clone(), canvas_config.clone()).await?;
         let mut canvas = canvas_config.canvas();
@@ -15,10 +15,13 @@ pub struct FavoriteListInfo {
     pub title: String,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize)]
 pub struct Upper<T> {
     pub mid: T,
+    // 番剧接口中的字段名为 uname 和 avatar
+    #[serde(alias = "uname")]
     pub name: String,
+    #[serde(alias = "avatar")]
     pub face: String,
 }
 impl<'a> FavoriteList<'a> {
@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Context, Result};
+use async_stream::try_stream;
+use futures::Stream;
+use reqwest::Method;
+use serde_json::Value;
+
+use crate::bilibili::{BiliClient, Validate};
+
+pub struct Following<'a> {
+    client: &'a BiliClient,
+    mid: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct FollowedUpper {
+    pub mid: i64,
+    #[serde(rename = "uname")]
+    pub name: String,
+    /// 该 UP 主所在的关注分组，未设置分组时接口返回 null
+    #[serde(default)]
+    pub tag: Option<Vec<i64>>,
+}
+
+impl FollowedUpper {
+    /// 判断该 UP 主是否位于指定的关注分组中，未设置分组的 UP 主属于默认分组（id 为 0）
+    pub fn in_tags(&self, tags: &[i64]) -> bool {
+        match &self.tag {
+            Some(tag) if !tag.is_empty() => tag.iter().any(|t| tags.contains(t)),
+            _ => tags.contains(&0),
+        }
+    }
+}
+
+impl<'a> Following<'a> {
+    pub fn new(client: &'a BiliClient, mid: String) -> Self {
+        Self { client, mid }
+    }
+
+    async fn get_followings(&self, page: i32) -> Result<Value> {
+        self.client
+            .request(Method::GET, "https://api.bilibili.com/x/relation/followings")
+            .await
+            .query(&[
+                ("vmid", self.mid.as_str()),
+                ("order", "desc"),
+                ("pn", page.to_string().as_str()),
+                ("ps", "50"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?
+            .validate()
+    }
+
+    pub fn into_upper_stream(self) -> impl Stream<Item = Result<FollowedUpper>> + 'a {
+        try_stream! {
+            let mut page = 1;
+            loop {
+                let mut followings = self
+                    .get_followings(page)
+                    .await
+                    .with_context(|| format!("failed to get followings of user {} page {}", self.mid, page))?;
+                let list = &mut followings["data"]["list"];
+                if list.as_array().is_none_or(|v| v.is_empty()) {
+                    break;
+                }
+                let uppers: Vec<FollowedUpper> = serde_json::from_value(list.take())
+                    .with_context(|| format!("failed to parse followings of user {} page {}", self.mid, page))?;
+                for upper in uppers {
+                    yield upper;
+                }
+                let total = &followings["data"]["total"];
+                if let Some(v) = total.as_i64() {
+                    if v > (page * 50) as i64 {
+                        page += 1;
+                        continue;
+                    }
+                } else {
+                    Err(anyhow!("total is not an i64"))?;
+                }
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_followed_upper_in_tags() {
+        let uppers: Vec<FollowedUpper> = serde_json::from_str(
+            r#"[
+                {"mid": 1, "uname": "a", "tag": null},
+                {"mid": 2, "uname": "b", "tag": [-10, 114]},
+                {"mid": 3, "uname": "c", "tag": [514]}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            uppers
+                .iter()
+                .filter(|u| u.in_tags(&[0]))
+                .map(|u| u.mid)
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+        assert_eq!(
+            uppers
+                .iter()
+                .filter(|u| u.in_tags(&[114, 514]))
+                .map(|u| u.mid)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert!(uppers.iter().all(|u| !u.in_tags(&[])));
+    }
+}
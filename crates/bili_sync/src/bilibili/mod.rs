@@ -1,8 +1,9 @@
 use std::sync::Arc;
 
-pub use analyzer::{BestStream, FilterOption};
+pub use analyzer::{AudioQuality, BestStream, FilterOption, Stream, StreamQuality, VideoQuality};
 use anyhow::{bail, ensure, Result};
 use arc_swap::ArcSwapOption;
+pub use bangumi::{Bangumi, BangumiItem, Season};
 use chrono::serde::ts_seconds;
 use chrono::{DateTime, Utc};
 pub use client::{BiliClient, Client};
@@ -12,20 +13,27 @@ pub use danmaku::DanmakuOption;
 pub use error::BiliError;
 pub use favorite_list::FavoriteList;
 use favorite_list::Upper;
+pub use following::Following;
 use once_cell::sync::Lazy;
+pub use resolver::{resolve, Resource};
 pub use submission::Submission;
-pub use video::{Dimension, PageInfo, Video};
+pub use user::{FavoriteFolder, User};
+pub use video::{parse_bvid, Chapter, Dimension, PageInfo, PlayerInfo, Video};
 pub use watch_later::WatchLater;
 
 mod analyzer;
+mod bangumi;
 mod client;
 mod collection;
 mod credential;
 mod danmaku;
 mod error;
 mod favorite_list;
+mod following;
+mod resolver;
 mod submission;
 mod subtitle;
+mod user;
 mod video;
 mod watch_later;
 
@@ -133,6 +141,15 @@ pub enum VideoInfo {
         #[serde(rename = "created", with = "ts_seconds")]
         ctime: DateTime<Utc>,
     },
+    /// 从番剧接口获取的剧集信息，由 Bangumi 手动构造，不参与反序列化
+    #[serde(skip)]
+    Bangumi {
+        title: String,
+        bvid: String,
+        ep_id: i64,
+        cover: String,
+        pubtime: DateTime<Utc>,
+    },
 }
 
 #[cfg(test)]
@@ -207,10 +224,10 @@ mod tests {
             panic!("获取 mixin key 失败");
         };
         set_global_mixin_key(mixin_key);
-        let video = Video::new(&bili_client, "BV1gLfnY8E6D".to_string());
+        let video = Video::new(&bili_client, "BV1gLfnY8E6D".to_string())?;
         let pages = video.get_pages().await?;
         println!("pages: {:?}", pages);
-        let subtitles = video.get_subtitles(&pages[0]).await?;
+        let subtitles = video.get_subtitles(&video.get_player_info(&pages[0]).await?).await?;
         for subtitle in subtitles {
             println!(
                 "{}: {}",
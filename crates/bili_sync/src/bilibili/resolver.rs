@@ -0,0 +1,229 @@
+use anyhow::{bail, Context, Result};
+use reqwest::{Method, Url};
+use serde_json::Value;
+
+use crate::bilibili::{BangumiItem, BiliClient, CollectionItem, CollectionType, Validate};
+
+/// 可以被同步的视频来源，与 Args 一一对应，但持有所有权
+#[derive(Debug, PartialEq)]
+pub enum Resource {
+    Favorite(String),
+    Collection(CollectionItem),
+    Submission(String),
+    WatchLater,
+    Bangumi(BangumiItem),
+}
+
+/// 仅通过链接本身解析出的结果，部分情况需要额外请求才能确定最终的来源
+#[derive(Debug, PartialEq)]
+enum Target {
+    Resource(Resource),
+    /// b23.tv 等短链接，需要跟随跳转后重新解析
+    ShortLink,
+    /// 链接中没有指明是视频合集还是视频列表
+    Collection {
+        mid: String,
+        sid: String,
+    },
+}
+
+/// 解析任意支持的链接，返回对应的视频来源
+pub async fn resolve(client: &BiliClient, input: &str) -> Result<Resource> {
+    let mut url = Url::parse(input).with_context(|| format!("invalid url {}", input))?;
+    let mut target = parse_url(&url)?;
+    if target == Target::ShortLink {
+        url = client
+            .request(Method::GET, url.as_str())
+            .await
+            .send()
+            .await?
+            .error_for_status()?
+            .url()
+            .clone();
+        target = parse_url(&url)?;
+    }
+    match target {
+        Target::Resource(resource) => Ok(resource),
+        Target::ShortLink => bail!("too many redirects when resolving {}", input),
+        Target::Collection { mid, sid } => {
+            let collection_type = detect_collection_type(client, &mid, &sid).await?;
+            Ok(Resource::Collection(CollectionItem {
+                mid,
+                sid,
+                collection_type,
+            }))
+        }
+    }
+}
+
+/// 视频合集与视频列表的 id 不共享，此处尝试按照视频列表查询，查询成功且作者一致时认为是视频列表，否则为视频合集
+async fn detect_collection_type(client: &BiliClient, mid: &str, sid: &str) -> Result<CollectionType> {
+    let res = client
+        .request(Method::GET, "https://api.bilibili.com/x/series/series")
+        .await
+        .query(&[("series_id", sid)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Value>()
+        .await?
+        .validate();
+    Ok(match res {
+        Ok(res) if res["data"]["meta"]["mid"].as_i64().map(|m| m.to_string()).as_deref() == Some(mid) => {
+            CollectionType::Series
+        }
+        _ => CollectionType::Season,
+    })
+}
+
+fn parse_url(url: &Url) -> Result<Target> {
+    let host = url.host_str().context("url has no host")?;
+    let segments = url
+        .path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let query = |key: &str| url.query_pairs().find(|(k, _)| k == key).map(|(_, v)| v.into_owned());
+    let target = match host {
+        "b23.tv" | "bili2233.cn" => Target::ShortLink,
+        "space.bilibili.com" => {
+            let mid = match segments.first() {
+                Some(mid) if mid.chars().all(|c| c.is_ascii_digit()) => mid.to_string(),
+                _ => bail!("failed to parse mid from {}", url),
+            };
+            match segments[1..] {
+                [] | ["video"] | ["upload", ..] => Target::Resource(Resource::Submission(mid)),
+                ["favlist"] => Target::Resource(Resource::Favorite(
+                    query("fid").with_context(|| format!("no fid found in {}", url))?,
+                )),
+                ["channel", kind] if kind == "collectiondetail" || kind == "seriesdetail" => {
+                    let sid = query("sid").with_context(|| format!("no sid found in {}", url))?;
+                    let collection_type = if kind == "collectiondetail" {
+                        CollectionType::Season
+                    } else {
+                        CollectionType::Series
+                    };
+                    Target::Resource(Resource::Collection(CollectionItem {
+                        mid,
+                        sid,
+                        collection_type,
+                    }))
+                }
+                ["lists", sid] => {
+                    let sid = sid.to_owned();
+                    match query("type").as_deref() {
+                        Some("season") => Target::Resource(Resource::Collection(CollectionItem {
+                            mid,
+                            sid,
+                            collection_type: CollectionType::Season,
+                        })),
+                        Some("series") => Target::Resource(Resource::Collection(CollectionItem {
+                            mid,
+                            sid,
+                            collection_type: CollectionType::Series,
+                        })),
+                        _ => Target::Collection { mid, sid },
+                    }
+                }
+                _ => bail!("unsupported space url {}", url),
+            }
+        }
+        "www.bilibili.com" | "bilibili.com" | "m.bilibili.com" => match segments.as_slice() {
+            ["watchlater", ..] | ["list", "watchlater", ..] => Target::Resource(Resource::WatchLater),
+            ["medialist", "detail", id] | ["list", id] if id.starts_with("ml") => {
+                Target::Resource(Resource::Favorite(id.trim_start_matches("ml").to_owned()))
+            }
+            ["list", mid] => Target::Collection {
+                mid: mid.to_string(),
+                sid: query("sid").with_context(|| format!("no sid found in {}", url))?,
+            },
+            ["bangumi", "play" | "media", id] => Target::Resource(Resource::Bangumi(id.parse()?)),
+            _ => bail!("unsupported url {}", url),
+        },
+        _ => bail!("unsupported host {}", host),
+    };
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(url: &str) -> Target {
+        parse_url(&Url::parse(url).unwrap()).unwrap()
+    }
+
+    fn collection(mid: &str, sid: &str, collection_type: CollectionType) -> Target {
+        Target::Resource(Resource::Collection(CollectionItem {
+            mid: mid.to_owned(),
+            sid: sid.to_owned(),
+            collection_type,
+        }))
+    }
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(
+            parse("https://space.bilibili.com/9183758/video"),
+            Target::Resource(Resource::Submission("9183758".to_owned()))
+        );
+        assert_eq!(
+            parse("https://space.bilibili.com/9183758/favlist?fid=3115878158&ftype=create"),
+            Target::Resource(Resource::Favorite("3115878158".to_owned()))
+        );
+        assert_eq!(
+            parse("https://www.bilibili.com/medialist/detail/ml3115878158"),
+            Target::Resource(Resource::Favorite("3115878158".to_owned()))
+        );
+        assert_eq!(
+            parse("https://space.bilibili.com/521722088/channel/collectiondetail?sid=387214"),
+            collection("521722088", "387214", CollectionType::Season)
+        );
+        assert_eq!(
+            parse("https://space.bilibili.com/521722088/channel/seriesdetail?sid=387214"),
+            collection("521722088", "387214", CollectionType::Series)
+        );
+        assert_eq!(
+            parse("https://space.bilibili.com/521722088/lists/387214?type=season"),
+            collection("521722088", "387214", CollectionType::Season)
+        );
+        assert_eq!(
+            parse("https://space.bilibili.com/521722088/lists/387214"),
+            Target::Collection {
+                mid: "521722088".to_owned(),
+                sid: "387214".to_owned()
+            }
+        );
+        assert_eq!(
+            parse("https://www.bilibili.com/list/521722088?sid=387214&oid=1"),
+            Target::Collection {
+                mid: "521722088".to_owned(),
+                sid: "387214".to_owned()
+            }
+        );
+        assert_eq!(
+            parse("https://www.bilibili.com/bangumi/play/ss12345"),
+            Target::Resource(Resource::Bangumi(BangumiItem::Season("12345".to_owned())))
+        );
+        assert_eq!(
+            parse("https://www.bilibili.com/bangumi/media/md28229233/"),
+            Target::Resource(Resource::Bangumi(BangumiItem::Media("28229233".to_owned())))
+        );
+        assert_eq!(
+            parse("https://www.bilibili.com/watchlater/#/list"),
+            Target::Resource(Resource::WatchLater)
+        );
+        assert_eq!(parse("https://b23.tv/abcdefg"), Target::ShortLink);
+        for invalid in [
+            "https://space.bilibili.com/abc",
+            "https://space.bilibili.com/9183758/favlist",
+            "https://www.bilibili.com/video/BV1Tr421n746",
+            "https://www.example.com/",
+        ] {
+            assert!(
+                parse_url(&Url::parse(invalid).unwrap()).is_err(),
+                "{} should be invalid",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{anyhow, Context, Result};
+use async_stream::try_stream;
+use futures::Stream;
+use reqwest::Method;
+use serde_json::Value;
+
+use crate::bilibili::favorite_list::Upper;
+use crate::bilibili::{BiliClient, CollectionItem, CollectionType, Resource, Validate};
+
+pub struct User<'a> {
+    client: &'a BiliClient,
+    mid: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct FavoriteFolder {
+    pub id: i64,
+    pub title: String,
+    pub media_count: i64,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CollectedFolder {
+    pub id: i64,
+    pub title: String,
+    /// 11 表示他人的收藏夹，21 表示他人创建的视频合集
+    #[serde(rename = "type")]
+    pub folder_type: i32,
+    pub upper: Upper<i64>,
+    pub media_count: i64,
+}
+
+impl CollectedFolder {
+    /// 收藏和订阅中的内容，可以直接对应到已有的收藏夹或视频合集
+    pub fn resource(&self) -> Option<Resource> {
+        match self.folder_type {
+            11 => Some(Resource::Favorite(self.id.to_string())),
+            21 => Some(Resource::Collection(CollectionItem {
+                mid: self.upper.mid.to_string(),
+                sid: self.id.to_string(),
+                collection_type: CollectionType::Season,
+            })),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> User<'a> {
+    pub fn new(client: &'a BiliClient, mid: String) -> Self {
+        Self { client, mid }
+    }
+
+    /// 获取该用户创建的所有收藏夹，查询他人时仅能获取到公开的收藏夹
+    pub async fn get_created_favorites(&self) -> Result<Vec<FavoriteFolder>> {
+        let mut res = self
+            .client
+            .request(Method::GET, "https://api.bilibili.com/x/v3/fav/folder/created/list-all")
+            .await
+            .query(&[("up_mid", self.mid.as_str())])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?
+            .validate()?;
+        // 没有任何收藏夹时 data 为 null
+        if res["data"].is_null() {
+            return Ok(Vec::new());
+        }
+        Ok(serde_json::from_value(res["data"]["list"].take())?)
+    }
+
+    async fn get_collected_favorites(&self, page: i32) -> Result<Value> {
+        self.client
+            .request(Method::GET, "https://api.bilibili.com/x/v3/fav/folder/collected/list")
+            .await
+            .query(&[
+                ("up_mid", self.mid.as_str()),
+                ("pn", page.to_string().as_str()),
+                ("ps", "20"),
+                ("platform", "web"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?
+            .validate()
+    }
+
+    /// 获取该用户“收藏和订阅”中的所有内容，包括他人的收藏夹与视频合集
+    pub fn into_collected_stream(self) -> impl Stream<Item = Result<CollectedFolder>> + 'a {
+        try_stream! {
+            let mut page = 1;
+            loop {
+                let mut collected = self
+                    .get_collected_favorites(page)
+                    .await
+                    .with_context(|| format!("failed to get collected favorites of user {} page {}", self.mid, page))?;
+                let list = &mut collected["data"]["list"];
+                if list.as_array().is_none_or(|v| v.is_empty()) {
+                    break;
+                }
+                let folders: Vec<CollectedFolder> = serde_json::from_value(list.take())
+                    .with_context(|| format!("failed to parse collected favorites of user {} page {}", self.mid, page))?;
+                for folder in folders {
+                    yield folder;
+                }
+                let has_more = &collected["data"]["has_more"];
+                if let Some(v) = has_more.as_bool() {
+                    if v {
+                        page += 1;
+                        continue;
+                    }
+                } else {
+                    Err(anyhow!("has_more is not a bool"))?;
+                }
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collected_folder() {
+        let folders: Vec<CollectedFolder> = serde_json::from_str(
+            r#"[
+                {"id": 1, "title": "a", "type": 11, "upper": {"mid": 100, "name": "x", "face": ""}, "media_count": 1},
+                {"id": 2, "title": "b", "type": 21, "upper": {"mid": 200, "name": "y", "face": ""}, "media_count": 1},
+                {"id": 3, "title": "c", "type": 0, "upper": {"mid": 300, "name": "z", "face": ""}, "media_count": 1}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            folders.iter().map(CollectedFolder::resource).collect::<Vec<_>>(),
+            vec![
+                Some(Resource::Favorite("1".to_owned())),
+                Some(Resource::Collection(CollectionItem {
+                    mid: "200".to_owned(),
+                    sid: "2".to_owned(),
+                    collection_type: CollectionType::Season,
+                })),
+                None,
+            ]
+        );
+    }
+}
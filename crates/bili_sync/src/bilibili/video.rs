@@ -1,7 +1,9 @@
-use anyhow::{ensure, Result};
+use anyhow::{bail, ensure, Context, Result};
 use futures::stream::FuturesUnordered;
 use futures::TryStreamExt;
+use once_cell::sync::Lazy;
 use prost::Message;
+use regex::Regex;
 use reqwest::Method;
 
 use crate::bilibili::analyzer::PageAnalyzer;
@@ -20,6 +22,19 @@ static DATA: &[char] = &[
     'm', 'U', 'S', 'D', 'Q', 'X', '9', 'R', 'd', 'o', 'Z', 'f',
 ];
 
+/// 播放器接口返回的分页信息，同一分页的章节与字幕都来自该接口，获取一次后复用
+pub struct PlayerInfo(serde_json::Value);
+
+impl PlayerInfo {
+    /// 获取分页的章节（即 UP 主设置的视频看点），没有设置章节时返回空列表
+    pub fn chapters(&self) -> Result<Vec<Chapter>> {
+        if self.0["view_points"].is_null() {
+            return Ok(Vec::new());
+        }
+        Ok(serde_json::from_value(self.0["view_points"].clone())?)
+    }
+}
+
 pub struct Video<'a> {
     client: &'a BiliClient,
     pub aid: String,
@@ -39,7 +54,7 @@ impl serde::Serialize for Tag {
         serializer.serialize_str(&self.tag_name)
     }
 }
-#[derive(Debug, serde::Deserialize, Default)]
+#[derive(Debug, Clone, serde::Deserialize, Default)]
 pub struct PageInfo {
     pub cid: i64,
     pub page: i32,
@@ -48,9 +63,20 @@ pub struct PageInfo {
     pub duration: u32,
     pub first_frame: Option<String>,
     pub dimension: Option<Dimension>,
+    /// 番剧剧集的 ep_id，存在时会使用 pgc 的接口获取视频流
+    #[serde(skip)]
+    pub ep_id: Option<i64>,
+}
+
+/// 视频的章节，起止时间的单位为秒
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Chapter {
+    pub from: u32,
+    pub to: u32,
+    pub content: String,
 }
 
-#[derive(Debug, serde::Deserialize, Default)]
+#[derive(Debug, Clone, serde::Deserialize, Default)]
 pub struct Dimension {
     pub width: u32,
     pub height: u32,
@@ -58,9 +84,9 @@ pub struct Dimension {
 }
 
 impl<'a> Video<'a> {
-    pub fn new(client: &'a BiliClient, bvid: String) -> Self {
-        let aid = bvid_to_aid(&bvid).to_string();
-        Self { client, aid, bvid }
+    pub fn new(client: &'a BiliClient, bvid: String) -> Result<Self> {
+        let aid = bvid_to_aid(&bvid)?.to_string();
+        Ok(Self { client, aid, bvid })
     }
 
     /// 直接调用视频信息接口获取详细的视频信息，视频信息中包含了视频的分页信息
@@ -79,7 +105,6 @@ impl<'a> Video<'a> {
         Ok(serde_json::from_value(res["data"].take())?)
     }
 
-    #[allow(unused)]
     pub async fn get_pages(&self) -> Result<Vec<PageInfo>> {
         let mut res = self
             .client
@@ -110,9 +135,9 @@ impl<'a> Video<'a> {
         Ok(serde_json::from_value(res["data"].take())?)
     }
 
-    pub async fn get_danmaku_writer(&self, page: &'a PageInfo) -> Result<DanmakuWriter> {
+    pub async fn get_danmaku_writer(&self, page: &'a PageInfo) -> Result<DanmakuWriter<'a>> {
         let tasks = FuturesUnordered::new();
-        for i in 1..=(page.duration + 359) / 360 {
+        for i in 1..=page.duration.div_ceil(360) {
             tasks.push(self.get_danmaku_segment(page, i as i64));
         }
         let result: Vec<Vec<DanmakuElem>> = tasks.try_collect().await?;
@@ -142,6 +167,9 @@ impl<'a> Video<'a> {
     }
 
     pub async fn get_page_analyzer(&self, page: &PageInfo) -> Result<PageAnalyzer> {
+        if let Some(ep_id) = page.ep_id {
+            return self.get_pgc_page_analyzer(page, ep_id).await;
+        }
         let mut res = self
             .client
             .request(Method::GET, "https://api.bilibili.com/x/player/wbi/playurl")
@@ -166,7 +194,32 @@ impl<'a> Video<'a> {
         Ok(PageAnalyzer::new(res["data"].take()))
     }
 
-    pub async fn get_subtitles(&self, page: &PageInfo) -> Result<Vec<SubTitle>> {
+    /// 番剧的视频流需要通过 pgc 接口获取，返回的结构与普通视频一致，但位于 result 字段中
+    async fn get_pgc_page_analyzer(&self, page: &PageInfo, ep_id: i64) -> Result<PageAnalyzer> {
+        let mut res = self
+            .client
+            .request(Method::GET, "https://api.bilibili.com/pgc/player/web/playurl")
+            .await
+            .query(&[
+                ("avid", self.aid.as_str()),
+                ("cid", page.cid.to_string().as_str()),
+                ("ep_id", ep_id.to_string().as_str()),
+                ("qn", "127"),
+                ("otype", "json"),
+                ("fnval", "4048"),
+                ("fourk", "1"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<serde_json::Value>()
+            .await?
+            .validate()?;
+        Ok(PageAnalyzer::new(res["result"].take()))
+    }
+
+    /// 播放器接口返回的分页信息，包含字幕与章节等内容
+    pub async fn get_player_info(&self, page: &PageInfo) -> Result<PlayerInfo> {
         let mut res = self
             .client
             .request(Method::GET, "https://api.bilibili.com/x/player/wbi/v2")
@@ -180,8 +233,12 @@ impl<'a> Video<'a> {
             .json::<serde_json::Value>()
             .await?
             .validate()?;
+        Ok(PlayerInfo(res["data"].take()))
+    }
+
+    pub async fn get_subtitles(&self, player_info: &PlayerInfo) -> Result<Vec<SubTitle>> {
         // 接口返回的信息，包含了一系列的字幕，每个字幕包含了字幕的语言和 json 下载地址
-        let subtitles_info: SubTitlesInfo = serde_json::from_value(res["data"]["subtitle"].take())?;
+        let subtitles_info: SubTitlesInfo = serde_json::from_value(player_info.0["subtitle"].clone())?;
         let tasks = subtitles_info
             .subtitles
             .into_iter()
@@ -205,16 +262,49 @@ impl<'a> Video<'a> {
     }
 }
 
-fn bvid_to_aid(bvid: &str) -> u64 {
+/// 从 BV 号、av 号或包含二者的视频链接中解析出 bvid
+pub fn parse_bvid(input: &str) -> Result<String> {
+    static BVID: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i:bv)(1[1-9A-HJ-NP-Za-km-z]{9})").expect("invalid regex"));
+    static AID: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|/)(?i:av)(\d+)").expect("invalid regex"));
+    if let Some(caps) = BVID.captures(input) {
+        return Ok(format!("BV{}", &caps[1]));
+    }
+    if let Some(aid) = AID.captures(input).and_then(|caps| caps[1].parse::<u64>().ok()) {
+        // 超出范围的 av 号会覆盖 bvid 的前缀，得到错误的 bvid
+        ensure!(aid <= MASK_CODE, "av number {} is out of range", aid);
+        return Ok(aid_to_bvid(aid));
+    }
+    bail!("failed to parse bvid from {}", input);
+}
+
+fn aid_to_bvid(aid: u64) -> String {
+    let mut bvid = ['B', 'V', '1', '0', '0', '0', '0', '0', '0', '0', '0', '0'];
+    let mut idx = bvid.len() - 1;
+    let mut tmp = ((MASK_CODE + 1) | aid) ^ XOR_CODE;
+    while tmp > 0 {
+        bvid[idx] = DATA[(tmp % BASE) as usize];
+        tmp /= BASE;
+        idx -= 1;
+    }
+    bvid.swap(3, 9);
+    bvid.swap(4, 7);
+    bvid.iter().collect()
+}
+
+fn bvid_to_aid(bvid: &str) -> Result<u64> {
     let mut bvid = bvid.chars().collect::<Vec<_>>();
+    ensure!(bvid.len() == 12, "invalid bvid length {}", bvid.len());
     (bvid[3], bvid[9]) = (bvid[9], bvid[3]);
     (bvid[4], bvid[7]) = (bvid[7], bvid[4]);
     let mut tmp = 0u64;
     for char in bvid.into_iter().skip(3) {
-        let idx = DATA.iter().position(|&x| x == char).expect("invalid bvid");
+        let idx = DATA
+            .iter()
+            .position(|&x| x == char)
+            .with_context(|| format!("invalid character {} in bvid", char))?;
         tmp = tmp * BASE + idx as u64;
     }
-    (tmp & MASK_CODE) ^ XOR_CODE
+    Ok((tmp & MASK_CODE) ^ XOR_CODE)
 }
 
 #[cfg(test)]
@@ -223,7 +313,31 @@ mod tests {
 
     #[test]
     fn test_bvid_to_aid() {
-        assert_eq!(bvid_to_aid("BV1Tr421n746"), 1401752220u64);
-        assert_eq!(bvid_to_aid("BV1sH4y1s7fe"), 1051892992u64);
+        assert_eq!(bvid_to_aid("BV1Tr421n746").unwrap(), 1401752220u64);
+        assert_eq!(bvid_to_aid("BV1sH4y1s7fe").unwrap(), 1051892992u64);
+        assert!(bvid_to_aid("BV1000000000").is_err());
+        assert!(bvid_to_aid("BV1Tr421").is_err());
+    }
+
+    #[test]
+    fn test_parse_bvid() {
+        assert_eq!(aid_to_bvid(1401752220), "BV1Tr421n746");
+        assert_eq!(aid_to_bvid(1051892992), "BV1sH4y1s7fe");
+        for (input, bvid) in [
+            ("BV1Tr421n746", "BV1Tr421n746"),
+            ("bv1Tr421n746", "BV1Tr421n746"),
+            ("av1401752220", "BV1Tr421n746"),
+            ("AV1051892992", "BV1sH4y1s7fe"),
+            ("https://www.bilibili.com/video/BV1sH4y1s7fe/?p=2", "BV1sH4y1s7fe"),
+            ("https://www.bilibili.com/video/av1051892992", "BV1sH4y1s7fe"),
+        ] {
+            assert_eq!(parse_bvid(input).unwrap(), bvid);
+        }
+        assert!(parse_bvid("https://www.bilibili.com/").is_err());
+        // 不属于 base58 字母表的字符与超出范围的 av 号
+        assert!(parse_bvid("BV1000000000").is_err());
+        assert!(parse_bvid("BV1Tr421n7Il").is_err());
+        assert!(parse_bvid(&format!("av{}", MASK_CODE + 1)).is_err());
+        assert_eq!(parse_bvid(&format!("av{}", MASK_CODE)).unwrap(), aid_to_bvid(MASK_CODE));
     }
 }
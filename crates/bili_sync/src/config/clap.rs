@@ -1,4 +1,6 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -6,6 +8,26 @@ pub struct Args {
     #[arg(short, long, env = "SCAN_ONLY")]
     pub scan_only: bool,
 
+    /// 启动后的第一轮扫描会完整遍历所有视频来源，补全此前遗漏的视频
+    #[arg(long, env = "FULL_RESCAN")]
+    pub full_rescan: bool,
+
     #[arg(short, long, default_value = "None,bili_sync=info", env = "RUST_LOG")]
     pub log_level: String,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// 单独下载指定的视频，支持 BV 号、av 号与视频链接
+    Download {
+        #[arg(required = true)]
+        videos: Vec<String>,
+
+        /// 视频的保存路径，默认为当前目录
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
 }
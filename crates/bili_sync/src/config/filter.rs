@@ -0,0 +1,190 @@
+use bili_sync_entity::video;
+use chrono::{NaiveDate, NaiveDateTime};
+use regex::Regex;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// 一组正则表达式，在配置文件中写作字符串数组
+#[derive(Default, Clone)]
+pub struct Patterns(Vec<Regex>);
+
+impl Patterns {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn find(&self, text: &str) -> Option<&Regex> {
+        self.0.iter().find(|regex| regex.is_match(text))
+    }
+}
+
+impl PartialEq for Patterns {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.iter().map(Regex::as_str).eq(other.0.iter().map(Regex::as_str))
+    }
+}
+
+impl Serialize for Patterns {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(Regex::as_str))
+    }
+}
+
+impl<'de> Deserialize<'de> for Patterns {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|pattern| Regex::new(pattern).map_err(D::Error::custom))
+            .collect::<Result<_, _>>()
+            .map(Patterns)
+    }
+}
+
+/// 视频内容的过滤规则，所有规则均满足的视频才会被下载
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct ContentFilter {
+    /// 标题需要匹配其中任意一个正则表达式，为空时不限制
+    #[serde(skip_serializing_if = "Patterns::is_empty")]
+    pub title_include: Patterns,
+    /// 标题不能匹配其中任何一个正则表达式
+    #[serde(skip_serializing_if = "Patterns::is_empty")]
+    pub title_exclude: Patterns,
+    #[serde(skip_serializing_if = "Patterns::is_empty")]
+    pub intro_include: Patterns,
+    #[serde(skip_serializing_if = "Patterns::is_empty")]
+    pub intro_exclude: Patterns,
+    /// 视频所有分页的总时长范围，单位为秒
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_duration: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_duration: Option<u32>,
+    /// 发布日期的范围，包含两端
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pubtime_after: Option<NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pubtime_before: Option<NaiveDate>,
+    /// 标签需要包含其中任意一个，为空时不限制
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags_include: Vec<String>,
+    /// 标签不能包含其中任何一个
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags_exclude: Vec<String>,
+    /// 仅下载这些 UP 主的视频，为空时不限制
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub upper_allow: Vec<i64>,
+    /// 不下载这些 UP 主的视频
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub upper_deny: Vec<i64>,
+}
+
+impl ContentFilter {
+    /// 检查视频的基本信息，返回被过滤的原因
+    /// 视频列表接口返回的信息并不完整，缺失的字段会被跳过，等待获取详情后再次检查
+    pub fn check_video(&self, video: &video::ActiveModel) -> Option<String> {
+        if let Some(title) = video.name.try_as_ref().filter(|title| !title.is_empty()) {
+            if !self.title_include.is_empty() && self.title_include.find(title).is_none() {
+                return Some("标题不匹配任何包含规则".to_owned());
+            }
+            if let Some(regex) = self.title_exclude.find(title) {
+                return Some(format!("标题匹配排除规则 {}", regex.as_str()));
+            }
+        }
+        if let Some(intro) = video.intro.try_as_ref().filter(|intro| !intro.is_empty()) {
+            if let Some(regex) = self.intro_exclude.find(intro) {
+                return Some(format!("简介匹配排除规则 {}", regex.as_str()));
+            }
+        }
+        if let Some(upper_id) = video.upper_id.try_as_ref().filter(|upper_id| **upper_id != 0) {
+            if !self.upper_allow.is_empty() && !self.upper_allow.contains(upper_id) {
+                return Some(format!("UP 主 {} 不在允许列表中", upper_id));
+            }
+            if self.upper_deny.contains(upper_id) {
+                return Some(format!("UP 主 {} 在禁止列表中", upper_id));
+            }
+        }
+        if let Some(pubtime) = video
+            .pubtime
+            .try_as_ref()
+            .filter(|pubtime| **pubtime != NaiveDateTime::default())
+        {
+            let date = pubtime.date();
+            if self.pubtime_after.is_some_and(|after| date < after) {
+                return Some(format!("发布日期 {} 早于限制", date));
+            }
+            if self.pubtime_before.is_some_and(|before| date > before) {
+                return Some(format!("发布日期 {} 晚于限制", date));
+            }
+        }
+        None
+    }
+
+    /// 检查视频的详细信息，返回被过滤的原因
+    pub fn check_detail(&self, video: &video::ActiveModel, tags: &[String], duration: u32) -> Option<String> {
+        if let Some(reason) = self.check_video(video) {
+            return Some(reason);
+        }
+        let intro = video.intro.try_as_ref().map(String::as_str).unwrap_or_default();
+        if !self.intro_include.is_empty() && self.intro_include.find(intro).is_none() {
+            return Some("简介不匹配任何包含规则".to_owned());
+        }
+        if self.min_duration.is_some_and(|min| duration < min) {
+            return Some(format!("时长 {} 秒短于限制", duration));
+        }
+        if self.max_duration.is_some_and(|max| duration > max) {
+            return Some(format!("时长 {} 秒长于限制", duration));
+        }
+        if !self.tags_include.is_empty() && !tags.iter().any(|tag| self.tags_include.contains(tag)) {
+            return Some("标签不包含任何需要的标签".to_owned());
+        }
+        if let Some(tag) = tags.iter().find(|tag| self.tags_exclude.contains(tag)) {
+            return Some(format!("标签包含 {}", tag));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::ActiveValue::Set;
+    use sea_orm::IntoActiveModel;
+
+    use super::*;
+
+    #[test]
+    fn test_content_filter() {
+        let filter: ContentFilter = toml::from_str(
+            r#"
+            title_include = ["教程", "(?i)rust"]
+            title_exclude = ["直播回放"]
+            min_duration = 60
+            pubtime_after = "2024-01-01"
+            tags_exclude = ["广告"]
+            upper_deny = [2]
+            "#,
+        )
+        .unwrap();
+        let mut video = video::Model::default().into_active_model();
+        // 列表中缺失的信息不会导致视频被过滤
+        assert_eq!(filter.check_video(&video), None);
+        video.name = Set("Rust 入门".to_owned());
+        video.pubtime = Set(NaiveDate::from_ymd_opt(2024, 5, 1).unwrap().into());
+        video.upper_id = Set(1);
+        assert_eq!(filter.check_video(&video), None);
+        assert_eq!(filter.check_detail(&video, &["编程".to_owned()], 600), None);
+        assert!(filter.check_detail(&video, &["广告".to_owned()], 600).is_some());
+        assert!(filter.check_detail(&video, &[], 30).is_some());
+        video.name = Set("Rust 直播回放".to_owned());
+        assert!(filter.check_video(&video).is_some());
+        video.name = Set("Go 入门".to_owned());
+        assert!(filter.check_video(&video).is_some());
+        video.name = Set("Rust 入门".to_owned());
+        video.upper_id = Set(2);
+        assert!(filter.check_video(&video).is_some());
+        video.upper_id = Set(1);
+        video.pubtime = Set(NaiveDate::from_ymd_opt(2023, 5, 1).unwrap().into());
+        assert!(filter.check_video(&video).is_some());
+        // 没有设置任何规则时序列化为空
+        assert_eq!(toml::to_string(&ContentFilter::default()).unwrap(), "");
+    }
+}
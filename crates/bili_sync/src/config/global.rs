@@ -11,7 +11,7 @@ use crate::config::Config;
 /// 全局的 CONFIG，可以从中读取配置信息
 pub static CONFIG: Lazy<Config> = Lazy::new(load_config);
 
-/// 全局的 TEMPLATE，用来渲染 video_name 和 page_name 模板
+/// 全局的 TEMPLATE，用来渲染 video_name、page_name 和 favorite_name 模板
 pub static TEMPLATE: Lazy<handlebars::Handlebars> = Lazy::new(|| {
     let mut handlebars = handlebars::Handlebars::new();
     handlebars_helper!(truncate: |s: String, len: usize| {
@@ -29,6 +29,22 @@ pub static TEMPLATE: Lazy<handlebars::Handlebars> = Lazy::new(|| {
         .path_safe_register("page", &CONFIG.page_name)
         .expect("failed to register page template");
     handlebars
+        .path_safe_register("favorite", &CONFIG.favorite_name)
+        .expect("failed to register favorite template");
+    // 视频来源单独设置的模板，自动发现的视频来源会沿用其配置项中的选项，因此在此处可以注册所有可能用到的模板
+    for source in CONFIG.sources() {
+        if let Some(video_name) = &source.options.video_name {
+            handlebars
+                .path_safe_register(&source.video_template(), video_name)
+                .expect("failed to register video template");
+        }
+        if let Some(page_name) = &source.options.page_name {
+            handlebars
+                .path_safe_register(&source.page_template(), page_name)
+                .expect("failed to register page template");
+        }
+    }
+    handlebars
 });
 
 /// 全局的 ARGS，用来解析命令行参数
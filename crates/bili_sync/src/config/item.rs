@@ -1,23 +1,422 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
+use reqwest::Url;
 use serde::de::{Deserializer, MapAccess, Visitor};
 use serde::ser::SerializeMap;
 use serde::{Deserialize, Serialize};
 
-use crate::bilibili::{CollectionItem, CollectionType};
+use crate::bilibili::{CollectionItem, CollectionType, DanmakuOption, FilterOption};
+use crate::config::{ContentFilter, CONFIG};
 use crate::utils::filenamify::filenamify;
 
+/// 视频来源的配置，不需要额外选项时可以直接写作保存路径，否则写作包含 path 的表格
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(from = "SourceConfigRaw", into = "SourceConfigRaw")]
+pub struct SourceConfig {
+    pub path: PathBuf,
+    pub options: SourceOption,
+}
+
+/// 视频来源可以单独设置的选项，未设置的选项使用全局配置
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SourceOption {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub removal_policy: Option<RemovalPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_rescan: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_filter: Option<ContentFilter>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter_option: Option<FilterOption>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub danmaku_option: Option<DanmakuOption>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nfo_time_type: Option<NFOTimeType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<RetentionPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputOption>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SourceConfigRaw {
+    Path(PathBuf),
+    Detailed {
+        path: PathBuf,
+        #[serde(flatten)]
+        options: Box<SourceOption>,
+    },
+}
+
+impl From<SourceConfigRaw> for SourceConfig {
+    fn from(value: SourceConfigRaw) -> Self {
+        match value {
+            SourceConfigRaw::Path(path) => path.into(),
+            SourceConfigRaw::Detailed { path, options } => Self {
+                path,
+                options: *options,
+            },
+        }
+    }
+}
+
+impl From<SourceConfig> for SourceConfigRaw {
+    fn from(value: SourceConfig) -> Self {
+        // 没有设置任何选项时保持原有的写法
+        if value.options == SourceOption::default() {
+            SourceConfigRaw::Path(value.path)
+        } else {
+            SourceConfigRaw::Detailed {
+                path: value.path,
+                options: Box::new(value.options),
+            }
+        }
+    }
+}
+
+impl From<PathBuf> for SourceConfig {
+    fn from(path: PathBuf) -> Self {
+        Self {
+            path,
+            options: SourceOption::default(),
+        }
+    }
+}
+
+impl SourceConfig {
+    /// 使用相同的选项，但保存在另一个路径下，用于自动发现的视频来源
+    pub fn with_path(&self, path: PathBuf) -> Self {
+        Self {
+            path,
+            options: self.options.clone(),
+        }
+    }
+
+    pub fn removal_policy(&self) -> &RemovalPolicy {
+        self.options.removal_policy.as_ref().unwrap_or(&CONFIG.removal_policy)
+    }
+
+    pub fn full_rescan(&self) -> bool {
+        self.options.full_rescan.unwrap_or(CONFIG.full_rescan)
+    }
+
+    /// 视频来源单独设置的过滤规则会完整覆盖全局的过滤规则
+    pub fn content_filter(&self) -> &ContentFilter {
+        self.options.content_filter.as_ref().unwrap_or(&CONFIG.content_filter)
+    }
+
+    pub fn filter_option(&self) -> &FilterOption {
+        self.options.filter_option.as_ref().unwrap_or(&CONFIG.filter_option)
+    }
+
+    pub fn danmaku_option(&self) -> &DanmakuOption {
+        self.options.danmaku_option.as_ref().unwrap_or(&CONFIG.danmaku_option)
+    }
+
+    pub fn nfo_time_type(&self) -> &NFOTimeType {
+        self.options.nfo_time_type.as_ref().unwrap_or(&CONFIG.nfo_time_type)
+    }
+
+    pub fn retention(&self) -> &RetentionPolicy {
+        self.options.retention.as_ref().unwrap_or(&CONFIG.retention)
+    }
+
+    pub fn output(&self) -> &OutputOption {
+        self.options.output.as_ref().unwrap_or(&CONFIG.output)
+    }
+
+    /// 渲染视频文件夹名称时使用的模板名，单独设置的模板以 video:{模板内容} 为名注册在 TEMPLATE 中
+    pub fn video_template(&self) -> Cow<'static, str> {
+        match &self.options.video_name {
+            Some(video_name) => Cow::Owned(format!("video:{}", video_name)),
+            None => Cow::Borrowed("video"),
+        }
+    }
+
+    /// 渲染分页文件名称时使用的模板名，规则与 video_template 相同
+    pub fn page_template(&self) -> Cow<'static, str> {
+        match &self.options.page_name {
+            Some(page_name) => Cow::Owned(format!("page:{}", page_name)),
+            None => Cow::Borrowed("page"),
+        }
+    }
+}
+
+/// 视频来源的保留策略，超出范围的视频会被删除且不再下载，未设置的规则不做限制
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// 仅保留最新的若干个视频
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_count: Option<usize>,
+    /// 仅保留该天数以内的视频
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+    /// 已下载内容的总大小上限，单位为 MB
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<u64>,
+    /// 仅打印将被删除的视频，不实际执行
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.max_count.is_none() && self.max_age.is_none() && self.max_size.is_none()
+    }
+}
+
+/// 同一视频出现在多个视频来源中时，复用已下载文件的方式
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupeMode {
+    /// 每个视频来源独立下载
+    #[default]
+    None,
+    Hardlink,
+    Symlink,
+    Reflink,
+}
+
+/// 合并 DASH 视频流与音频流的方式
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Remuxer {
+    /// 使用内置的实现，失败时回退到 FFmpeg
+    #[default]
+    Builtin,
+    Ffmpeg,
+}
+
+/// 分页视频文件的输出格式
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Mp4,
+    /// 将字幕、弹幕、章节、封面与元数据嵌入到同一个 Matroska 文件中，需要 FFmpeg
+    Mkv,
+    /// 仅下载音频，输出包含标签与封面的 m4a 或 flac 文件，需要 FFmpeg
+    Audio,
+}
+
+impl OutputFormat {
+    /// 该格式的文件可能的扩展名，第一项为默认的扩展名
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            OutputFormat::Mp4 => &["mp4"],
+            OutputFormat::Mkv => &["mkv"],
+            OutputFormat::Audio => &["m4a", "flac"],
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        self.extensions()[0]
+    }
+
+    /// 文件是否为该格式的输出
+    pub fn matches(&self, path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|extension| self.extensions().iter().any(|e| extension == *e))
+    }
+}
+
+/// 分页视频文件的输出设置
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct OutputOption {
+    pub format: OutputFormat,
+    /// 输出 MKV 或音频时是否保留已经嵌入的字幕、弹幕与封面文件
+    pub sidecar: bool,
+}
+
+impl Default for OutputOption {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::default(),
+            sidecar: true,
+        }
+    }
+}
+
+/// 下载完成后使用 ffprobe 校验视频文件的完整性
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct VerifyOption {
+    pub enabled: bool,
+    /// 允许的时长误差，单位为秒
+    pub duration_tolerance: u32,
+}
+
+impl Default for VerifyOption {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            duration_tolerance: 3,
+        }
+    }
+}
+
+/// 下载视频流时对 CDN 节点的偏好
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct CdnOption {
+    /// 优先使用域名包含这些字符串的链接，越靠前越优先
+    pub prefer: Vec<String>,
+    /// 将 upos 节点链接的域名替换为这些域名，作为额外的候选链接优先尝试
+    pub rewrite: Vec<String>,
+}
+
+impl CdnOption {
+    /// 根据偏好对流的所有链接排序，返回按顺序尝试的候选链接
+    pub fn candidates(&self, urls: &[String]) -> Vec<String> {
+        let mut candidates: Vec<String> = Vec::new();
+        let rewritten = self.rewrite.iter().flat_map(|host| {
+            urls.iter().filter_map(move |url| {
+                let mut url = Url::parse(url).ok()?;
+                if !url.host_str()?.starts_with("upos-") {
+                    return None;
+                }
+                url.set_host(Some(host)).ok()?;
+                Some(url.to_string())
+            })
+        });
+        for url in rewritten.chain(urls.iter().cloned()) {
+            if !candidates.contains(&url) {
+                candidates.push(url);
+            }
+        }
+        let priority = |url: &String| {
+            let host = Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_owned));
+            host.and_then(|host| self.prefer.iter().position(|prefer| host.contains(prefer.as_str())))
+                .unwrap_or(self.prefer.len())
+        };
+        candidates.sort_by_key(priority);
+        candidates
+    }
+}
+
+/// 视频被移出视频列表后的处理方式
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RemovalPolicy {
+    /// 不检测视频是否被移出
+    #[default]
+    Ignore,
+    /// 检测并记录，但保留已下载的内容
+    Keep,
+    /// 将已下载的内容移动到归档目录
+    Archive,
+    /// 删除已下载的内容与数据库记录
+    Delete,
+}
+
+/// 定期检查已下载视频是否新增分页的配置
+#[derive(Serialize, Deserialize)]
+pub struct PageRecheckConfig {
+    pub enabled: bool,
+    /// 仅检查发布时间在该天数以内的视频
+    pub max_age: u64,
+    /// 同一视频两次检查之间的最小间隔，单位为秒
+    pub interval: u64,
+}
+
+impl Default for PageRecheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_age: 7,
+            interval: 86400,
+        }
+    }
+}
+
+/// 定期刷新已下载视频元数据的配置
+#[derive(Serialize, Deserialize)]
+pub struct MetadataRefreshConfig {
+    pub enabled: bool,
+    /// 同一视频两次刷新之间的最小间隔，单位为秒
+    pub interval: u64,
+}
+
+impl Default for MetadataRefreshConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 604800,
+        }
+    }
+}
+
+/// 定期检查近期发布的视频是否有更好的视频流，并自动重新下载的配置
+#[derive(Serialize, Deserialize)]
+pub struct QualityUpgradeConfig {
+    pub enabled: bool,
+    /// 仅检查发布时间在该天数以内的视频
+    pub max_age: u64,
+    /// 同一分页两次检查之间的最小间隔，单位为秒
+    pub interval: u64,
+}
+
+impl Default for QualityUpgradeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_age: 3,
+            interval: 21600,
+        }
+    }
+}
+
 /// 稍后再看的配置
 #[derive(Serialize, Deserialize, Default)]
 pub struct WatchLaterConfig {
     pub enabled: bool,
-    pub path: PathBuf,
+    pub path: SourceConfig,
 }
 
-/// NFO 文件使用的时间类型
+/// 同步“收藏和订阅”的配置
+#[derive(Serialize, Deserialize, Default)]
+pub struct CollectedConfig {
+    pub enabled: bool,
+    /// 每个收藏夹或视频合集会保存在该路径下以其名称命名的文件夹中
+    pub path: SourceConfig,
+}
+
+/// 自动订阅关注列表的配置
 #[derive(Serialize, Deserialize, Default)]
+pub struct FollowingConfig {
+    pub enabled: bool,
+    /// 每个 UP 主的投稿会保存在该路径下以 UP 主名称命名的文件夹中
+    pub path: SourceConfig,
+    /// 仅订阅位于这些关注分组中的 UP 主，为空时订阅全部
+    #[serde(default)]
+    pub tags: Vec<i64>,
+    #[serde(default)]
+    pub unfollow_policy: UnfollowPolicy,
+}
+
+/// 取消关注 UP 主后的处理方式
+#[derive(Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnfollowPolicy {
+    /// 停止同步，保留已下载的内容
+    #[default]
+    Ignore,
+    /// 继续同步，与仍在关注时相同
+    Keep,
+}
+
+/// NFO 文件使用的时间类型
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum NFOTimeType {
     #[default]
@@ -31,6 +430,8 @@ pub struct ConcurrentLimit {
     pub video: usize,
     pub page: usize,
     pub rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    pub download: DownloadLimit,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -39,6 +440,24 @@ pub struct RateLimit {
     pub duration: u64,
 }
 
+/// 单个文件的分段下载配置
+#[derive(Serialize, Deserialize)]
+pub struct DownloadLimit {
+    /// 单个文件同时使用的连接数，为 1 时不分段下载
+    pub connections: usize,
+    /// 每个分段的大小，单位为 MB，小于该大小的文件不分段下载
+    pub chunk_size: u64,
+}
+
+impl Default for DownloadLimit {
+    fn default() -> Self {
+        Self {
+            connections: 1,
+            chunk_size: 16,
+        }
+    }
+}
+
 impl Default for ConcurrentLimit {
     fn default() -> Self {
         Self {
@@ -49,28 +468,29 @@ impl Default for ConcurrentLimit {
                 limit: 4,
                 duration: 250,
             }),
+            download: DownloadLimit::default(),
         }
     }
 }
 
 pub trait PathSafeTemplate {
-    fn path_safe_register(&mut self, name: &'static str, template: &'static str) -> Result<()>;
-    fn path_safe_render(&self, name: &'static str, data: &serde_json::Value) -> Result<String>;
+    fn path_safe_register(&mut self, name: &str, template: &str) -> Result<()>;
+    fn path_safe_render(&self, name: &str, data: &serde_json::Value) -> Result<String>;
 }
 
 /// 通过将模板字符串中的分隔符替换为自定义的字符串，使得模板字符串中的分隔符得以保留
 impl PathSafeTemplate for handlebars::Handlebars<'_> {
-    fn path_safe_register(&mut self, name: &'static str, template: &'static str) -> Result<()> {
+    fn path_safe_register(&mut self, name: &str, template: &str) -> Result<()> {
         Ok(self.register_template_string(name, template.replace(std::path::MAIN_SEPARATOR_STR, "__SEP__"))?)
     }
 
-    fn path_safe_render(&self, name: &'static str, data: &serde_json::Value) -> Result<String> {
+    fn path_safe_render(&self, name: &str, data: &serde_json::Value) -> Result<String> {
         Ok(filenamify(&self.render(name, data)?).replace("__SEP__", std::path::MAIN_SEPARATOR_STR))
     }
 }
 /* 后面是用于自定义 Collection 的序列化、反序列化的样板代码 */
 pub(super) fn serialize_collection_list<S>(
-    collection_list: &HashMap<CollectionItem, PathBuf>,
+    collection_list: &HashMap<CollectionItem, SourceConfig>,
     serializer: S,
 ) -> Result<S::Ok, S::Error>
 where
@@ -87,14 +507,16 @@ where
     map.end()
 }
 
-pub(super) fn deserialize_collection_list<'de, D>(deserializer: D) -> Result<HashMap<CollectionItem, PathBuf>, D::Error>
+pub(super) fn deserialize_collection_list<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<CollectionItem, SourceConfig>, D::Error>
 where
     D: Deserializer<'de>,
 {
     struct CollectionListVisitor;
 
     impl<'de> Visitor<'de> for CollectionListVisitor {
-        type Value = HashMap<CollectionItem, PathBuf>;
+        type Value = HashMap<CollectionItem, SourceConfig>;
 
         fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
             formatter.write_str("a map of collection list")
@@ -105,7 +527,7 @@ where
             A: MapAccess<'de>,
         {
             let mut collection_list = HashMap::new();
-            while let Some((key, value)) = map.next_entry::<String, PathBuf>()? {
+            while let Some((key, value)) = map.next_entry::<String, SourceConfig>()? {
                 let collection_item = match key.split(':').collect::<Vec<&str>>().as_slice() {
                     [prefix, mid, sid] => {
                         let collection_type = match *prefix {
@@ -137,3 +559,59 @@ where
 
     deserializer.deserialize_map(CollectionListVisitor)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_config() {
+        let list: HashMap<String, SourceConfig> = toml::from_str(
+            r#"
+            1 = "/tmp/a"
+            2 = { path = "/tmp/b", removal_policy = "archive" }
+            3 = { path = "/tmp/c", content_filter = { title_exclude = ["直播回放"] } }
+            4 = { path = "/tmp/d", video_name = "{{bvid}}", danmaku_option = { enabled = false } }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(list["1"].path, PathBuf::from("/tmp/a"));
+        assert!(list["1"].options.removal_policy.is_none());
+        assert_eq!(list["2"].path, PathBuf::from("/tmp/b"));
+        assert_eq!(list["2"].options.removal_policy, Some(RemovalPolicy::Archive));
+        assert!(list["2"].options.content_filter.is_none());
+        assert!(list["3"].options.content_filter.is_some());
+        assert_eq!(list["1"].video_template(), "video");
+        assert_eq!(list["4"].video_template(), "video:{{bvid}}");
+        assert_eq!(list["4"].page_template(), "page");
+        // 单独设置的选项中未填写的字段使用默认值
+        let danmaku_option = list["4"].options.danmaku_option.as_ref().unwrap();
+        assert!(!danmaku_option.enabled);
+        assert_eq!(danmaku_option.font_size, DanmakuOption::default().font_size);
+        // 没有设置选项时序列化为原有的写法
+        let serialized = toml::to_string(&list).unwrap();
+        assert!(serialized.contains(r#"1 = "/tmp/a""#));
+        assert!(serialized.contains(r#"removal_policy = "archive""#));
+    }
+
+    #[test]
+    fn test_cdn_candidates() {
+        let urls = vec![
+            "https://upos-sz-mirrorcos.bilivideo.com/a.m4s?e=1".to_owned(),
+            "https://cn-gdfs-ct-01-01.bilivideo.com/a.m4s?e=1".to_owned(),
+        ];
+        assert_eq!(CdnOption::default().candidates(&urls), urls);
+        let option = CdnOption {
+            prefer: vec!["cn-gdfs".to_owned()],
+            rewrite: vec!["upos-sz-mirrorali.bilivideo.com".to_owned()],
+        };
+        assert_eq!(
+            option.candidates(&urls),
+            [
+                "https://cn-gdfs-ct-01-01.bilivideo.com/a.m4s?e=1",
+                "https://upos-sz-mirrorali.bilivideo.com/a.m4s?e=1",
+                "https://upos-sz-mirrorcos.bilivideo.com/a.m4s?e=1",
+            ]
+        );
+    }
+}
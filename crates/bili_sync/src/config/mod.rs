@@ -8,37 +8,123 @@ use arc_swap::ArcSwapOption;
 use serde::{Deserialize, Serialize};
 
 mod clap;
+mod filter;
 mod global;
 mod item;
+mod schedule;
 
-use crate::bilibili::{CollectionItem, Credential, DanmakuOption, FilterOption};
+use crate::bilibili::{BangumiItem, CollectionItem, Credential, DanmakuOption, FilterOption};
+pub use crate::config::clap::Command;
+pub use crate::config::filter::ContentFilter;
 pub use crate::config::global::{ARGS, CONFIG, CONFIG_DIR, TEMPLATE};
 use crate::config::item::{deserialize_collection_list, serialize_collection_list, ConcurrentLimit};
-pub use crate::config::item::{NFOTimeType, PathSafeTemplate, RateLimit, WatchLaterConfig};
+pub use crate::config::item::{
+    CdnOption, CollectedConfig, DedupeMode, FollowingConfig, MetadataRefreshConfig, NFOTimeType, OutputFormat,
+    OutputOption, PageRecheckConfig, PathSafeTemplate, QualityUpgradeConfig, RateLimit, RemovalPolicy, Remuxer,
+    RetentionPolicy, SourceConfig, SourceOption, UnfollowPolicy, VerifyOption, WatchLaterConfig,
+};
+pub use crate::config::schedule::{BandwidthLimit, ScheduleConfig, WindowEndPolicy};
 
 fn default_time_format() -> String {
     "%Y-%m-%d".to_string()
 }
 
+fn default_favorite_name() -> Cow<'static, str> {
+    Cow::Borrowed("{{title}}")
+}
+
+/// 除 url_list 外，同样可以使用链接作为键的视频来源
+const URL_KEYED_LISTS: [&str; 4] = ["favorite_list", "collection_list", "submission_list", "bangumi_list"];
+
+fn is_url(key: &str) -> bool {
+    key.starts_with("https://") || key.starts_with("http://")
+}
+
+/// 将视频来源中以链接作为键的项移动到 url_list 中统一解析，返回链接与其原本所在的视频来源
+fn extract_url_keys(table: &mut toml::Table) -> HashMap<String, String> {
+    let mut url_origins = HashMap::new();
+    let mut moved = Vec::new();
+    for list in URL_KEYED_LISTS {
+        let Some(toml::Value::Table(sources)) = table.get_mut(list) else {
+            continue;
+        };
+        for (url, source) in sources.iter().filter(|(key, _)| is_url(key)) {
+            url_origins.insert(url.clone(), list.to_owned());
+            moved.push((url.clone(), source.clone()));
+        }
+        // 使用 retain 删除以保持其余项的顺序
+        sources.retain(|key, _| !is_url(key));
+    }
+    if !moved.is_empty() {
+        let url_list = table
+            .entry("url_list")
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        if let toml::Value::Table(url_list) = url_list {
+            url_list.extend(moved);
+        }
+    }
+    url_origins
+}
+
+/// extract_url_keys 的逆操作，保存配置时将链接写回原本所在的视频来源
+fn restore_url_keys(table: &mut toml::Table, url_origins: &HashMap<String, String>) {
+    let Some(toml::Value::Table(url_list)) = table.get_mut("url_list") else {
+        return;
+    };
+    let moved = url_list
+        .iter()
+        .filter_map(|(url, source)| Some((url_origins.get(url)?, url.clone(), source.clone())))
+        .collect::<Vec<_>>();
+    url_list.retain(|url, _| !url_origins.contains_key(url));
+    for (list, url, source) in moved {
+        if let toml::Value::Table(sources) = table
+            .entry(list.as_str())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+        {
+            sources.insert(url, source);
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub credential: ArcSwapOption<Credential>,
     pub filter_option: FilterOption,
     #[serde(default)]
     pub danmaku_option: DanmakuOption,
-    pub favorite_list: HashMap<String, PathBuf>,
+    #[serde(default)]
+    pub content_filter: ContentFilter,
+    pub favorite_list: HashMap<String, SourceConfig>,
+    #[serde(default)]
+    pub favorite_user_list: HashMap<String, SourceConfig>,
     #[serde(
         default,
         serialize_with = "serialize_collection_list",
         deserialize_with = "deserialize_collection_list"
     )]
-    pub collection_list: HashMap<CollectionItem, PathBuf>,
+    pub collection_list: HashMap<CollectionItem, SourceConfig>,
+    #[serde(default)]
+    pub submission_list: HashMap<String, SourceConfig>,
     #[serde(default)]
-    pub submission_list: HashMap<String, PathBuf>,
+    pub bangumi_list: HashMap<BangumiItem, SourceConfig>,
+    #[serde(default)]
+    pub url_list: HashMap<String, SourceConfig>,
     #[serde(default)]
     pub watch_later: WatchLaterConfig,
+    #[serde(default)]
+    pub following: FollowingConfig,
+    #[serde(default)]
+    pub collected: CollectedConfig,
+    #[serde(default)]
+    pub page_recheck: PageRecheckConfig,
+    #[serde(default)]
+    pub metadata_refresh: MetadataRefreshConfig,
+    #[serde(default)]
+    pub quality_upgrade: QualityUpgradeConfig,
     pub video_name: Cow<'static, str>,
     pub page_name: Cow<'static, str>,
+    #[serde(default = "default_favorite_name")]
+    pub favorite_name: Cow<'static, str>,
     pub interval: u64,
     pub upper_path: PathBuf,
     #[serde(default)]
@@ -47,6 +133,31 @@ pub struct Config {
     pub concurrent_limit: ConcurrentLimit,
     #[serde(default = "default_time_format")]
     pub time_format: String,
+    #[serde(default)]
+    pub removal_policy: RemovalPolicy,
+    #[serde(default)]
+    pub archive_path: PathBuf,
+    #[serde(default)]
+    pub full_rescan: bool,
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+    #[serde(default)]
+    pub dedupe: DedupeMode,
+    #[serde(default)]
+    pub remuxer: Remuxer,
+    #[serde(default)]
+    pub cdn: CdnOption,
+    #[serde(default)]
+    pub bandwidth_limit: BandwidthLimit,
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+    #[serde(default)]
+    pub verify: VerifyOption,
+    #[serde(default)]
+    pub output: OutputOption,
+    /// 在其它视频来源中以链接作为键、加载时被移动到 url_list 中的项，保存时写回原处
+    #[serde(skip)]
+    url_origins: HashMap<String, String>,
 }
 
 impl Default for Config {
@@ -54,27 +165,63 @@ impl Default for Config {
         Self {
             credential: ArcSwapOption::from(Some(Arc::new(Credential::default()))),
             filter_option: FilterOption::default(),
+            content_filter: ContentFilter::default(),
             danmaku_option: DanmakuOption::default(),
             favorite_list: HashMap::new(),
+            favorite_user_list: HashMap::new(),
             collection_list: HashMap::new(),
             submission_list: HashMap::new(),
+            bangumi_list: HashMap::new(),
+            url_list: HashMap::new(),
             watch_later: Default::default(),
+            following: Default::default(),
+            collected: Default::default(),
+            page_recheck: Default::default(),
+            metadata_refresh: Default::default(),
+            quality_upgrade: Default::default(),
             video_name: Cow::Borrowed("{{title}}"),
             page_name: Cow::Borrowed("{{bvid}}"),
+            favorite_name: default_favorite_name(),
             interval: 1200,
             upper_path: CONFIG_DIR.join("upper_face"),
             nfo_time_type: NFOTimeType::FavTime,
             concurrent_limit: ConcurrentLimit::default(),
             time_format: default_time_format(),
+            removal_policy: RemovalPolicy::default(),
+            archive_path: PathBuf::new(),
+            full_rescan: false,
+            retention: RetentionPolicy::default(),
+            dedupe: DedupeMode::default(),
+            remuxer: Remuxer::default(),
+            cdn: CdnOption::default(),
+            bandwidth_limit: BandwidthLimit::default(),
+            schedule: ScheduleConfig::default(),
+            verify: VerifyOption::default(),
+            output: OutputOption::default(),
+            url_origins: HashMap::new(),
         }
     }
 }
 
 impl Config {
+    /// 配置文件中的所有视频来源
+    pub fn sources(&self) -> impl Iterator<Item = &SourceConfig> {
+        self.favorite_list
+            .values()
+            .chain(self.favorite_user_list.values())
+            .chain(self.collection_list.values())
+            .chain(self.submission_list.values())
+            .chain(self.bangumi_list.values())
+            .chain(self.url_list.values())
+            .chain([&self.watch_later.path, &self.following.path, &self.collected.path])
+    }
+
     pub fn save(&self) -> Result<()> {
         let config_path = CONFIG_DIR.join("config.toml");
         std::fs::create_dir_all(&*CONFIG_DIR)?;
-        std::fs::write(config_path, toml::to_string_pretty(self)?)?;
+        let mut table = toml::Table::try_from(self)?;
+        restore_url_keys(&mut table, &self.url_origins);
+        std::fs::write(config_path, toml::to_string_pretty(&table)?)?;
         Ok(())
     }
 
@@ -82,28 +229,105 @@ impl Config {
     fn load() -> Result<Self> {
         let config_path = CONFIG_DIR.join("config.toml");
         let config_content = std::fs::read_to_string(config_path)?;
-        Ok(toml::from_str(&config_content)?)
+        Self::parse(&config_content)
+    }
+
+    #[cfg(not(test))]
+    fn parse(content: &str) -> Result<Self> {
+        let mut table: toml::Table = toml::from_str(content)?;
+        let url_origins = extract_url_keys(&mut table);
+        let mut config: Self = table.try_into()?;
+        config.url_origins = url_origins;
+        Ok(config)
+    }
+
+    /// 是否有视频来源使用 archive 移除策略，视频来源单独设置的策略优先于全局的策略
+    fn archive_required(&self) -> bool {
+        let mut sources = self
+            .favorite_list
+            .values()
+            .chain(self.favorite_user_list.values())
+            .chain(self.collection_list.values())
+            .chain(self.submission_list.values())
+            .chain(self.bangumi_list.values())
+            .chain(self.url_list.values())
+            .chain(self.watch_later.enabled.then_some(&self.watch_later.path))
+            .chain(self.following.enabled.then_some(&self.following.path))
+            .chain(self.collected.enabled.then_some(&self.collected.path));
+        sources.any(|source| {
+            source.options.removal_policy.as_ref().unwrap_or(&self.removal_policy) == &RemovalPolicy::Archive
+        })
     }
 
     #[cfg(not(test))]
     pub fn check(&self) {
         let mut ok = true;
-        if self.favorite_list.is_empty() && self.collection_list.is_empty() && !self.watch_later.enabled {
+        if self.favorite_list.is_empty()
+            && self.favorite_user_list.is_empty()
+            && self.collection_list.is_empty()
+            && self.submission_list.is_empty()
+            && self.bangumi_list.is_empty()
+            && self.url_list.is_empty()
+            && !self.watch_later.enabled
+            && !self.following.enabled
+            && !self.collected.enabled
+            // 单独下载视频时不需要配置扫描的内容
+            && ARGS.command.is_none()
+        {
             ok = false;
             error!("没有配置任何需要扫描的内容，程序空转没有意义");
         }
-        if self.watch_later.enabled && !self.watch_later.path.is_absolute() {
+        if self.watch_later.enabled && !self.watch_later.path.path.is_absolute() {
             error!(
                 "稍后再看保存的路径应为绝对路径，检测到：{}",
-                self.watch_later.path.display()
+                self.watch_later.path.path.display()
+            );
+        }
+        if self.following.enabled && !self.following.path.path.is_absolute() {
+            ok = false;
+            error!(
+                "自动订阅关注保存的路径应为绝对路径，检测到：{}",
+                self.following.path.path.display()
             );
         }
-        for path in self.favorite_list.values() {
+        if self.collected.enabled && !self.collected.path.path.is_absolute() {
+            ok = false;
+            error!(
+                "收藏和订阅保存的路径应为绝对路径，检测到：{}",
+                self.collected.path.path.display()
+            );
+        }
+        for SourceConfig { path, .. } in self.favorite_list.values() {
             if !path.is_absolute() {
                 ok = false;
                 error!("收藏夹保存的路径应为绝对路径，检测到: {}", path.display());
             }
         }
+        for SourceConfig { path, .. } in self.favorite_user_list.values() {
+            if !path.is_absolute() {
+                ok = false;
+                error!("用户收藏夹保存的路径应为绝对路径，检测到: {}", path.display());
+            }
+        }
+        for SourceConfig { path, .. } in self.bangumi_list.values() {
+            if !path.is_absolute() {
+                ok = false;
+                error!("番剧保存的路径应为绝对路径，检测到: {}", path.display());
+            }
+        }
+        for SourceConfig { path, .. } in self.url_list.values() {
+            if !path.is_absolute() {
+                ok = false;
+                error!("链接保存的路径应为绝对路径，检测到: {}", path.display());
+            }
+        }
+        if !self.archive_path.as_os_str().is_empty() && !self.archive_path.is_absolute() {
+            ok = false;
+            error!("归档的路径应为绝对路径，检测到: {}", self.archive_path.display());
+        } else if self.archive_required() && self.archive_path.as_os_str().is_empty() {
+            ok = false;
+            error!("存在使用 archive 移除策略的视频来源，但未设置 archive_path");
+        }
         if !self.upper_path.is_absolute() {
             ok = false;
             error!("up 主头像保存的路径应为绝对路径");
@@ -116,6 +340,10 @@ impl Config {
             ok = false;
             error!("未设置 page_name 模板");
         }
+        if self.favorite_name.is_empty() {
+            ok = false;
+            error!("未设置 favorite_name 模板");
+        }
         let credential = self.credential.load();
         match credential.as_deref() {
             Some(credential) => {
@@ -138,6 +366,10 @@ impl Config {
             ok = false;
             error!("video 和 page 允许的并发数必须大于 0");
         }
+        if !(self.concurrent_limit.download.connections > 0 && self.concurrent_limit.download.chunk_size > 0) {
+            ok = false;
+            error!("分段下载的连接数与分段大小必须大于 0");
+        }
         if !ok {
             panic!(
                 "位于 {} 的配置文件不合法，请参考提示信息修复后继续运行",
@@ -146,3 +378,83 @@ impl Config {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_required() {
+        let mut config = Config::default();
+        config.favorite_list.insert(
+            "1".to_owned(),
+            SourceConfig {
+                path: PathBuf::from("/bili-sync/收藏夹"),
+                options: Default::default(),
+            },
+        );
+        assert!(!config.archive_required());
+        config.removal_policy = RemovalPolicy::Archive;
+        assert!(config.archive_required());
+        // 视频来源单独设置的策略优先
+        config.favorite_list.get_mut("1").unwrap().options.removal_policy = Some(RemovalPolicy::Keep);
+        assert!(!config.archive_required());
+        config.removal_policy = RemovalPolicy::Keep;
+        config.submission_list.insert(
+            "2".to_owned(),
+            SourceConfig {
+                path: PathBuf::from("/bili-sync/投稿"),
+                options: SourceOption {
+                    removal_policy: Some(RemovalPolicy::Archive),
+                    ..Default::default()
+                },
+            },
+        );
+        assert!(config.archive_required());
+    }
+
+    #[test]
+    fn test_url_keys() {
+        let content = r#"
+            [favorite_list]
+            3115878158 = "/bili-sync/收藏夹"
+            "https://space.bilibili.com/9183758/favlist?fid=1" = "/bili-sync/链接收藏夹"
+
+            [collection_list]
+            "https://space.bilibili.com/521722088/lists/387214?type=season" = "/bili-sync/合集"
+            "season:521722088:387215" = "/bili-sync/另一个合集"
+
+            [bangumi_list]
+            "https://www.bilibili.com/bangumi/play/ss12345" = "/bili-sync/番剧"
+        "#;
+        let mut table: toml::Table = toml::from_str(content).unwrap();
+        let url_origins = extract_url_keys(&mut table);
+        assert_eq!(url_origins.len(), 3);
+        assert_eq!(
+            url_origins["https://www.bilibili.com/bangumi/play/ss12345"],
+            "bangumi_list"
+        );
+        let url_list = table["url_list"].as_table().unwrap();
+        assert_eq!(url_list.len(), 3);
+        assert_eq!(table["favorite_list"].as_table().unwrap().len(), 1);
+        assert_eq!(table["collection_list"].as_table().unwrap().len(), 1);
+        assert!(table["bangumi_list"].as_table().unwrap().is_empty());
+        // 保存时写回原本所在的视频来源
+        let mut restored = table.clone();
+        restore_url_keys(&mut restored, &url_origins);
+        assert!(restored["url_list"].as_table().unwrap().is_empty());
+        assert_eq!(
+            restored["collection_list"].as_table().unwrap()
+                ["https://space.bilibili.com/521722088/lists/387214?type=season"]
+                .as_str(),
+            Some("/bili-sync/合集")
+        );
+        assert_eq!(restored["favorite_list"].as_table().unwrap().len(), 2);
+        // 经过 Table 中转保存的配置与直接序列化的结果一致
+        let config = Config::default();
+        assert_eq!(
+            toml::to_string_pretty(&toml::Table::try_from(&config).unwrap()).unwrap(),
+            toml::to_string_pretty(&config).unwrap()
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use chrono::{Datelike, Days, Local, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+
+/// 一天中的时间段，包含开始时间、不包含结束时间，结束时间早于开始时间时表示跨越午夜，两者相同时表示全天
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start == self.end {
+            true
+        } else if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+/// 每周中若干天的时间段，跨越午夜的时间段以开始的那一天为准
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ScheduleWindow {
+    #[serde(flatten)]
+    pub window: TimeWindow,
+    /// 生效的星期，为空时每天生效
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub weekdays: Vec<Weekday>,
+}
+
+impl ScheduleWindow {
+    pub fn contains(&self, datetime: NaiveDateTime) -> bool {
+        let time = datetime.time();
+        if !self.window.contains(time) {
+            return false;
+        }
+        if self.weekdays.is_empty() {
+            return true;
+        }
+        // 跨越午夜的时间段在午夜之后的部分属于前一天
+        let date = if self.window.start > self.window.end && time < self.window.end {
+            datetime.date() - Days::new(1)
+        } else {
+            datetime.date()
+        };
+        self.weekdays.contains(&date.weekday())
+    }
+}
+
+/// 允许执行某项任务的时间，需要位于 allow 中的某个时间段内（allow 为空时不限制），且不位于 deny 中的任何时间段内
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct Schedule {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<ScheduleWindow>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<ScheduleWindow>,
+}
+
+impl Schedule {
+    pub fn is_allowed(&self, datetime: NaiveDateTime) -> bool {
+        (self.allow.is_empty() || self.allow.iter().any(|window| window.contains(datetime)))
+            && !self.deny.iter().any(|window| window.contains(datetime))
+    }
+}
+
+/// 下载时间段结束时正在进行的下载的处理方式
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowEndPolicy {
+    /// 继续执行直到当前视频来源的下载完成
+    #[default]
+    Finish,
+    /// 中断正在下载的文件，保留已下载的部分，在下一个时间段开始后继续
+    Pause,
+}
+
+/// 扫描与下载的时间安排
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    pub scan: Schedule,
+    pub download: Schedule,
+    pub on_window_end: WindowEndPolicy,
+}
+
+impl ScheduleConfig {
+    /// 当前是否允许扫描视频来源
+    pub fn scan_allowed(&self) -> bool {
+        self.scan.is_allowed(Local::now().naive_local())
+    }
+
+    /// 当前是否允许下载视频
+    pub fn download_allowed(&self) -> bool {
+        self.download.is_allowed(Local::now().naive_local())
+    }
+}
+
+/// 媒体文件下载的带宽限制，由所有同时进行的下载共享，单位为 KB/s
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct BandwidthLimit {
+    /// 不在任何时间段内时的限制，不设置时不限速
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+    /// 按时间段设置的限制，多个时间段重叠时使用靠前的
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<BandwidthProfile>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BandwidthProfile {
+    #[serde(flatten)]
+    pub window: TimeWindow,
+    /// 该时间段内的限制，不设置时不限速
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+}
+
+impl BandwidthLimit {
+    /// 获取指定时间的带宽限制，单位为字节每秒
+    pub fn bytes_per_second(&self, time: NaiveTime) -> Option<u64> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.window.contains(time))
+            .map_or(self.limit, |profile| profile.limit)
+            .map(|limit| limit * 1024)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn test_bandwidth_limit() {
+        let limit: BandwidthLimit = toml::from_str(
+            r#"
+            limit = 2048
+            profiles = [
+                { start = "01:00", end = "07:00" },
+                { start = "22:00", end = "01:00", limit = 4096 },
+            ]
+            "#,
+        )
+        .unwrap();
+        let time = |hour, min| NaiveTime::from_hms_opt(hour, min, 0).unwrap();
+        assert_eq!(limit.bytes_per_second(time(12, 0)), Some(2048 * 1024));
+        assert_eq!(limit.bytes_per_second(time(1, 0)), None);
+        assert_eq!(limit.bytes_per_second(time(6, 59)), None);
+        assert_eq!(limit.bytes_per_second(time(7, 0)), Some(2048 * 1024));
+        assert_eq!(limit.bytes_per_second(time(23, 30)), Some(4096 * 1024));
+        assert_eq!(limit.bytes_per_second(time(0, 30)), Some(4096 * 1024));
+        assert_eq!(BandwidthLimit::default().bytes_per_second(time(12, 0)), None);
+    }
+
+    #[test]
+    fn test_schedule() {
+        let schedule: Schedule = toml::from_str(
+            r#"
+            allow = [{ start = "22:00", end = "07:00" }, { start = "00:00", end = "00:00", weekdays = ["Sat", "Sun"] }]
+            deny = [{ start = "23:00", end = "02:00", weekdays = ["Fri"] }]
+            "#,
+        )
+        .unwrap();
+        // 2024-05-01 为星期三
+        let datetime = |day, hour| {
+            NaiveDate::from_ymd_opt(2024, 5, day)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap()
+        };
+        assert!(schedule.is_allowed(datetime(1, 23)));
+        assert!(schedule.is_allowed(datetime(2, 6)));
+        assert!(!schedule.is_allowed(datetime(2, 12)));
+        // 星期五晚间被禁止，禁止的时间段延续到星期六凌晨
+        assert!(!schedule.is_allowed(datetime(3, 23)));
+        assert!(!schedule.is_allowed(datetime(4, 1)));
+        assert!(schedule.is_allowed(datetime(4, 2)));
+        assert!(schedule.is_allowed(datetime(4, 12)));
+        assert!(Schedule::default().is_allowed(datetime(2, 12)));
+    }
+}
@@ -1,46 +1,400 @@
 use core::str;
-use std::path::Path;
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
 
-use anyhow::{bail, ensure, Result};
-use futures::TryStreamExt;
-use reqwest::Method;
-use tokio::fs::{self, File};
+use anyhow::{bail, ensure, Context, Result};
+use chrono::Local;
+use futures::{stream, StreamExt, TryStreamExt};
+use once_cell::sync::Lazy;
+use reqwest::{header, Method, StatusCode};
+use tokio::fs::{self, File, OpenOptions};
 use tokio::io::AsyncWriteExt;
-use tokio_util::io::StreamReader;
+use tokio::time::{self, Instant};
 
 use crate::bilibili::Client;
+use crate::config::{Remuxer, WindowEndPolicy, CONFIG};
+use crate::error::DownloadPausedError;
+use crate::utils::remux::remux;
+
+/// 单个文件连续下载失败的最大次数
+const MAX_RETRIES: u32 = 5;
+
+/// 所有下载共享的带宽限制
+static BANDWIDTH: Lazy<Bandwidth> = Lazy::new(Bandwidth::default);
+
+/// 简单的带宽限制实现，按照当前的速率为每块数据预约传输的时间，速率随时间段变化时立即生效
+#[derive(Default)]
+struct Bandwidth {
+    next: Mutex<Option<Instant>>,
+}
+
+impl Bandwidth {
+    async fn acquire(&self, bytes: u64) {
+        let Some(rate) = CONFIG.bandwidth_limit.bytes_per_second(Local::now().time()) else {
+            return;
+        };
+        let delay = {
+            let now = Instant::now();
+            let mut next = self.next.lock().expect("bandwidth lock poisoned");
+            let start = next.map_or(now, |next| next.max(now));
+            *next = Some(start + Duration::from_secs_f64(bytes as f64 / rate.max(1) as f64));
+            start - now
+        };
+        if !delay.is_zero() {
+            time::sleep(delay).await;
+        }
+    }
+}
+
+/// 重试无法解决的错误，如链接失效导致的 403
+#[derive(Debug, thiserror::Error)]
+#[error("unexpected status code {0}")]
+struct FatalError(StatusCode);
+
 pub struct Downloader {
     client: Client,
+    /// 单个文件同时使用的连接数
+    connections: usize,
+    /// 每个分段的大小，单位为字节
+    chunk_size: u64,
+    /// 是否在允许下载的时间段结束时暂停下载
+    pause_outside_window: bool,
 }
 
 impl Downloader {
     // Downloader 使用带有默认 Header 的 Client 构建
     // 拿到 url 后下载文件不需要任何 cookie 作为身份凭证
     // 但如果不设置默认 Header，下载时会遇到 403 Forbidden 错误
-    pub fn new(client: Client) -> Self {
-        Self { client }
+    // scheduled 表示下载是否受时间安排的限制
+    pub fn new(client: Client, scheduled: bool) -> Self {
+        let download = &CONFIG.concurrent_limit.download;
+        Self {
+            client,
+            connections: download.connections,
+            chunk_size: download.chunk_size * 1024 * 1024,
+            pause_outside_window: scheduled && CONFIG.schedule.on_window_end == WindowEndPolicy::Pause,
+        }
+    }
+
+    /// 是否已经离开允许下载的时间段，此时需要中断下载
+    /// 中断而非原地等待，避免阻塞之后的扫描，同时视频流的链接在等待期间会过期，需要在继续下载时重新获取
+    fn window_ended(&self) -> bool {
+        self.pause_outside_window && !CONFIG.schedule.download_allowed()
     }
 
+    /// 下载文件到指定路径，下载过程中写入同目录下的 `.part` 文件，完成后再重命名为目标文件
+    /// 下载出错时会通过 Range 请求从已写入的位置继续下载，上次运行遗留的 `.part` 文件在确认属于同一文件后同样会被继续使用
     pub async fn fetch(&self, url: &str, path: &Path) -> Result<()> {
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent).await?;
         }
-        let mut file = File::create(path).await?;
-        let resp = self.client.request(Method::GET, url, None).send().await?;
-        let expected = resp.content_length().unwrap_or_default();
-        let mut stream_reader = StreamReader::new(resp.bytes_stream().map_err(std::io::Error::other));
-        let received = tokio::io::copy(&mut stream_reader, &mut file).await?;
+        self.check_parts(url, path).await?;
+        let part_path = part_path(path, 0);
+        match self.segment_total(url, path).await? {
+            Some(total) => self.fetch_segments(url, path, total).await?,
+            None => self.fetch_range(url, path, &part_path, 0, None).await?,
+        }
+        fs::rename(&part_path, path).await?;
+        let _ = fs::remove_file(validator_path(path)).await;
+        Ok(())
+    }
+
+    /// 检查上次运行遗留的 `.part` 文件是否与当前的视频流一致，两次运行之间视频流可能因为升级画质或修改过滤选项而改变
+    /// 通过 If-Range 携带记录的 ETag 或 Last-Modified 请求第一个字节，服务器返回完整内容时说明文件已经改变，丢弃所有的 `.part` 文件
+    async fn check_parts(&self, url: &str, path: &Path) -> Result<()> {
+        if !fs::try_exists(part_path(path, 0)).await?
+            && !fs::try_exists(part_path(path, 1)).await?
+            && !fs::try_exists(validator_path(path)).await?
+        {
+            return Ok(());
+        }
+        let unchanged = match fs::read_to_string(validator_path(path)).await {
+            Ok(validator) => {
+                let resp = self
+                    .client
+                    .request(Method::GET, url, None)
+                    .header(header::ACCEPT_ENCODING, "identity")
+                    .header(header::RANGE, "bytes=0-0")
+                    .header(header::IF_RANGE, validator)
+                    .send()
+                    .await?;
+                match resp.status() {
+                    StatusCode::PARTIAL_CONTENT => true,
+                    status if status.is_success() => false,
+                    // 链接失效等错误无法说明文件是否改变，保留已下载的部分
+                    status if status.is_client_error() => return Err(FatalError(status).into()),
+                    status => bail!("unexpected status code {}", status),
+                }
+            }
+            // 没有记录校验值时无法确认已下载的部分属于哪个文件
+            Err(e) if e.kind() == ErrorKind::NotFound => false,
+            Err(e) => return Err(e.into()),
+        };
+        if !unchanged {
+            warn!("{} 已下载的部分与当前的文件不一致，将重新下载", path.display());
+            remove_parts(path).await?;
+        }
+        Ok(())
+    }
+
+    /// 依次尝试视频流的候选链接，直到其中一个下载成功，候选链接的顺序受 CDN 偏好影响
+    /// 不同链接指向的是同一文件，切换链接时已经下载的部分会被继续使用
+    pub async fn multi_fetch(&self, urls: &[String], path: &Path) -> Result<()> {
+        let candidates = CONFIG.cdn.candidates(urls);
+        let mut last_err = None;
+        for url in &candidates {
+            match self.fetch(url, path).await {
+                Ok(_) => return Ok(()),
+                Err(e) if e.downcast_ref::<DownloadPausedError>().is_some() => return Err(e),
+                Err(e) => {
+                    warn!("使用链接 {} 下载 {} 失败：{:#}", url, path.display(), e);
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(last_err.context("no stream url available")?)
+    }
+
+    /// 判断文件是否需要分段下载，需要时返回文件的总大小
+    async fn segment_total(&self, url: &str, path: &Path) -> Result<Option<u64>> {
+        if self.connections <= 1 {
+            return Ok(None);
+        }
+        // 存在单连接下载遗留的 `.part` 文件时继续使用单连接下载，避免丢弃已下载的部分
+        if fs::try_exists(part_path(path, 0)).await? && !fs::try_exists(part_path(path, 1)).await? {
+            return Ok(None);
+        }
+        // 请求第一个字节以确认服务器是否支持 Range 请求，失败时交由单连接下载重试
+        let Ok(resp) = self
+            .client
+            .request(Method::GET, url, None)
+            .header(header::ACCEPT_ENCODING, "identity")
+            .header(header::RANGE, "bytes=0-0")
+            .send()
+            .await
+        else {
+            return Ok(None);
+        };
+        if resp.status() != StatusCode::PARTIAL_CONTENT {
+            return Ok(None);
+        }
+        let total = resp
+            .headers()
+            .get(header::CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_content_range)
+            .and_then(|(_, total)| total);
+        Ok(total.filter(|total| *total > self.chunk_size))
+    }
+
+    /// 将文件按照分段大小切分后并发下载，每个分段写入单独的 `.part{n}` 文件，全部完成后按顺序拼接到 `.part` 文件中
+    async fn fetch_segments(&self, url: &str, path: &Path, total: u64) -> Result<()> {
+        let segments = (0..total)
+            .step_by(self.chunk_size as usize)
+            .enumerate()
+            .map(|(idx, begin)| (part_path(path, idx), begin, (begin + self.chunk_size).min(total) - 1))
+            .collect::<Vec<_>>();
+        stream::iter(segments.clone())
+            .map(|(segment_path, begin, end)| async move {
+                self.fetch_range(url, path, &segment_path, begin, Some(end)).await
+            })
+            .buffer_unordered(self.connections)
+            .try_collect::<Vec<_>>()
+            .await?;
+        let mut file = OpenOptions::new().append(true).open(&segments[0].0).await?;
+        for (segment_path, _, _) in &segments[1..] {
+            tokio::io::copy(&mut File::open(segment_path).await?, &mut file).await?;
+        }
         file.flush().await?;
-        ensure!(
-            received >= expected,
-            "received {} bytes, expected {} bytes",
-            received,
-            expected
-        );
+        for (segment_path, _, _) in &segments[1..] {
+            fs::remove_file(segment_path).await?;
+        }
         Ok(())
     }
 
+    /// 下载文件中 begin 到 end（包含）的部分，未指定 end 时下载到文件末尾，失败时自动重试
+    async fn fetch_range(&self, url: &str, path: &Path, part_path: &Path, begin: u64, end: Option<u64>) -> Result<()> {
+        let mut failures = 0;
+        loop {
+            if self.window_ended() {
+                bail!(DownloadPausedError());
+            }
+            match self.fetch_part(url, path, part_path, begin, end).await {
+                Ok(true) => return Ok(()),
+                // 取得了进展但尚未完成，不计入失败次数
+                Ok(false) => failures = 0,
+                Err(e) => {
+                    failures += 1;
+                    if failures >= MAX_RETRIES
+                        || e.downcast_ref::<FatalError>().is_some()
+                        || e.downcast_ref::<DownloadPausedError>().is_some()
+                    {
+                        return Err(e);
+                    }
+                    warn!(
+                        "下载 {} 失败：{:#}，将在 {} 秒后继续下载",
+                        part_path.display(),
+                        e,
+                        failures
+                    );
+                    time::sleep(Duration::from_secs(failures as u64)).await;
+                }
+            }
+        }
+    }
+
+    /// 从 `.part` 文件的末尾继续下载，返回文件是否已经完整
+    async fn fetch_part(&self, url: &str, path: &Path, part_path: &Path, begin: u64, end: Option<u64>) -> Result<bool> {
+        let offset = match fs::metadata(part_path).await {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        if end.is_some_and(|end| begin + offset > end + 1) {
+            // 分段文件超出了分段的范围，可能是上次拼接时中断导致的，丢弃后重新下载
+            fs::remove_file(part_path).await?;
+            bail!("segment exceeds range, discard {} bytes downloaded", offset);
+        }
+        // 显式指定不压缩，避免 Range 作用在压缩后的内容上
+        let mut req = self
+            .client
+            .request(Method::GET, url, None)
+            .header(header::ACCEPT_ENCODING, "identity");
+        if begin + offset > 0 || end.is_some() {
+            let end = end.map(|end| end.to_string()).unwrap_or_default();
+            req = req.header(header::RANGE, format!("bytes={}-{}", begin + offset, end));
+        }
+        // 继续下载时要求文件未发生变化，否则服务器会返回完整的内容
+        if offset > 0 {
+            match fs::read_to_string(validator_path(path)).await {
+                Ok(validator) => req = req.header(header::IF_RANGE, validator),
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let resp = req.send().await?;
+        // 从头开始下载时记录文件的校验值，用于之后继续下载时确认文件未发生变化
+        let restarted = match resp.status() {
+            StatusCode::PARTIAL_CONTENT => offset == 0,
+            StatusCode::OK => end.is_none(),
+            _ => false,
+        };
+        if begin == 0 && restarted {
+            match validator(resp.headers()) {
+                Some(validator) => fs::write(validator_path(path), validator).await?,
+                None => {
+                    let _ = fs::remove_file(validator_path(path)).await;
+                }
+            }
+        }
+        let (mut file, size) = match resp.status() {
+            StatusCode::PARTIAL_CONTENT => {
+                let (start, total) = resp
+                    .headers()
+                    .get(header::CONTENT_RANGE)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(parse_content_range)
+                    .context("invalid content-range header")?;
+                ensure!(
+                    start == begin + offset,
+                    "content-range starts at {}, expected {}",
+                    start,
+                    begin + offset
+                );
+                let size = match end {
+                    Some(end) => Some(end + 1 - begin),
+                    None => total.map(|total| total - begin),
+                };
+                (
+                    OpenOptions::new().create(true).append(true).open(part_path).await?,
+                    size,
+                )
+            }
+            StatusCode::RANGE_NOT_SATISFIABLE => {
+                let total = resp
+                    .headers()
+                    .get(header::CONTENT_RANGE)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.strip_prefix("bytes */"))
+                    .and_then(|value| value.parse::<u64>().ok());
+                if end.is_none() && total == Some(begin + offset) {
+                    return Ok(true);
+                }
+                // 已下载的部分与远程文件不一致，丢弃后从头下载
+                fs::remove_file(part_path).await?;
+                bail!("range not satisfiable, discard {} bytes downloaded", offset);
+            }
+            status if status.is_success() => {
+                // 服务器不支持 Range 请求或文件已经改变，只能从头下载，分段下载时丢弃该分段后重试
+                if begin > 0 || end.is_some() {
+                    let _ = fs::remove_file(part_path).await;
+                    bail!("server ignored range request, discard {} bytes downloaded", offset);
+                }
+                (File::create(part_path).await?, resp.content_length())
+            }
+            status if status.is_client_error() => {
+                return Err(FatalError(status).into());
+            }
+            status => bail!("unexpected status code {}", status),
+        };
+        let mut stream = resp.bytes_stream();
+        let (mut received, mut paused) = (0, false);
+        let res: Result<()> = async {
+            while let Some(chunk) = stream.try_next().await? {
+                if self.window_ended() {
+                    // 断开连接，保留已下载的部分，等待下一个时间段开始后继续
+                    paused = true;
+                    break;
+                }
+                BANDWIDTH.acquire(chunk.len() as u64).await;
+                file.write_all(&chunk).await?;
+                received += chunk.len() as u64;
+            }
+            Ok(())
+        }
+        .await;
+        file.flush().await?;
+        res?;
+        if paused {
+            bail!(DownloadPausedError());
+        }
+        match size {
+            Some(size) => {
+                let downloaded = file.metadata().await?.len();
+                ensure!(
+                    downloaded <= size,
+                    "received {} bytes, expected {} bytes",
+                    downloaded,
+                    size
+                );
+                // 没有收到任何数据时视为失败，避免无限重试
+                ensure!(downloaded == size || received > 0, "no data received");
+                Ok(downloaded == size)
+            }
+            // 未知文件大小时，只能以连接正常结束作为完成的标志
+            None => Ok(true),
+        }
+    }
+
     pub async fn merge(&self, video_path: &Path, audio_path: &Path, output_path: &Path) -> Result<()> {
+        if CONFIG.remuxer == Remuxer::Builtin {
+            let (video, audio, output) = (
+                video_path.to_path_buf(),
+                audio_path.to_path_buf(),
+                output_path.to_path_buf(),
+            );
+            match tokio::task::spawn_blocking(move || remux(&video, &audio, &output)).await? {
+                Ok(_) => return Ok(()),
+                Err(e) => warn!(
+                    "使用内置实现合并 {} 失败：{:#}，尝试使用 FFmpeg",
+                    output_path.display(),
+                    e
+                ),
+            }
+        }
         let output = tokio::process::Command::new("ffmpeg")
             .args([
                 "-i",
@@ -60,3 +414,120 @@ impl Downloader {
         Ok(())
     }
 }
+
+/// 下载过程中使用的临时文件路径，即在目标文件名后追加 `.part`，分段下载时其余分段追加 `.part{n}`
+fn part_path(path: &Path, idx: usize) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    match idx {
+        0 => name.push(".part"),
+        idx => name.push(format!(".part{}", idx)),
+    }
+    path.with_file_name(name)
+}
+
+/// 记录下载文件校验值的路径，同一文件的所有分段共用
+fn validator_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".part.validator");
+    path.with_file_name(name)
+}
+
+/// 从响应头中获取可用于 If-Range 的校验值，弱 ETag 不能用于 If-Range，此时使用 Last-Modified
+fn validator(headers: &header::HeaderMap) -> Option<String> {
+    headers
+        .get(header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| headers.get(header::LAST_MODIFIED)?.to_str().ok())
+        .map(str::to_owned)
+}
+
+/// 删除下载文件遗留的所有 `.part` 文件与校验值
+pub async fn remove_parts(path: &Path) -> Result<()> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(());
+    };
+    if !fs::try_exists(dir).await? {
+        return Ok(());
+    }
+    let prefix = format!("{}.part", name.to_string_lossy());
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if let Some(suffix) = file_name.strip_prefix(&prefix) {
+            // 仅匹配 `.part`、`.part{n}` 与校验值文件
+            if suffix.is_empty() || suffix == ".validator" || suffix.bytes().all(|b| b.is_ascii_digit()) {
+                fs::remove_file(entry.path()).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 解析形如 `bytes 100-199/1000` 的 Content-Range，返回起始位置与文件的总大小
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+    let total = match total {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    Some((start.parse().ok()?, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(parse_content_range("bytes 100-199/1000"), Some((100, Some(1000))));
+        assert_eq!(parse_content_range("bytes 0-0/*"), Some((0, None)));
+        assert_eq!(parse_content_range("bytes */1000"), None);
+        assert_eq!(parse_content_range("100-199/1000"), None);
+        assert_eq!(
+            part_path(Path::new("/tmp/video.mp4"), 0),
+            PathBuf::from("/tmp/video.mp4.part")
+        );
+        assert_eq!(
+            part_path(Path::new("/tmp/video.mp4"), 2),
+            PathBuf::from("/tmp/video.mp4.part2")
+        );
+    }
+
+    #[test]
+    fn test_validator() {
+        let mut headers = header::HeaderMap::new();
+        assert_eq!(validator(&headers), None);
+        headers.insert(header::LAST_MODIFIED, "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap());
+        assert_eq!(validator(&headers).as_deref(), Some("Wed, 21 Oct 2015 07:28:00 GMT"));
+        // 弱 ETag 不能用于 If-Range
+        headers.insert(header::ETAG, "W/\"abc\"".parse().unwrap());
+        assert_eq!(validator(&headers).as_deref(), Some("Wed, 21 Oct 2015 07:28:00 GMT"));
+        headers.insert(header::ETAG, "\"abc\"".parse().unwrap());
+        assert_eq!(validator(&headers).as_deref(), Some("\"abc\""));
+    }
+
+    #[tokio::test]
+    async fn test_remove_parts() {
+        let dir = std::env::temp_dir().join(format!("bili-sync-parts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("video.mp4");
+        for file in [
+            "video.mp4.part",
+            "video.mp4.part1",
+            "video.mp4.part.validator",
+            "video.mp4.parts.mp4",
+        ] {
+            std::fs::write(dir.join(file), b"").unwrap();
+        }
+        remove_parts(&path).await.unwrap();
+        let mut remaining = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(remaining, ["video.mp4.parts.mp4"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -7,3 +7,8 @@ pub struct DownloadAbortError();
 #[derive(Error, Debug)]
 #[error("Process page error")]
 pub struct ProcessPageError();
+
+/// 离开允许下载的时间段时中断下载，已下载的部分会被保留，在下一个时间段重新获取视频流后继续
+#[derive(Error, Debug)]
+#[error("Download window ended")]
+pub struct DownloadPausedError();
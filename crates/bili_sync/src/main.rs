@@ -10,25 +10,33 @@ mod error;
 mod utils;
 mod workflow;
 
+use std::collections::HashMap;
 use std::io;
-use std::path::PathBuf;
+use std::path::Path;
 
 use once_cell::sync::Lazy;
 use sea_orm::DatabaseConnection;
 use tokio::{signal, time};
 
 use crate::adapter::Args;
-use crate::bilibili::BiliClient;
-use crate::config::{ARGS, CONFIG};
+use crate::bilibili::{resolve, BiliClient, Resource};
+use crate::config::{Command, SourceConfig, ARGS, CONFIG};
 use crate::database::{database_connection, migrate_database};
 use crate::utils::init_logger;
-use crate::workflow::process_video_list;
+use crate::workflow::{
+    download_adhoc_videos, process_video_list, refresh_collected_list, refresh_following_list,
+    refresh_user_favorite_list,
+};
 
 #[tokio::main]
 async fn main() {
     init();
     let connection = setup_database().await;
     let bili_client = BiliClient::new();
+    if let Some(Command::Download { videos, output }) = &ARGS.command {
+        download_videos(videos, output.as_deref(), &bili_client, &connection).await;
+        return;
+    }
     let params = collect_task_params();
     let task = spawn_periodic_task(bili_client, params, connection);
     handle_shutdown(task).await;
@@ -47,8 +55,37 @@ async fn setup_database() -> DatabaseConnection {
     database_connection().await.expect("获取数据库连接失败")
 }
 
+/// 单独下载命令行中指定的视频，执行完毕后直接退出
+async fn download_videos(
+    videos: &[String],
+    output: Option<&Path>,
+    bili_client: &BiliClient,
+    connection: &DatabaseConnection,
+) {
+    let path = match output.map_or_else(std::env::current_dir, std::path::absolute) {
+        Ok(path) => path,
+        Err(e) => {
+            error!("获取视频保存路径遇到错误：{e}");
+            return;
+        }
+    };
+    match download_adhoc_videos(videos, bili_client, &path, connection).await {
+        Ok(videos) => {
+            for (name, video_path) in videos {
+                if video_path.is_empty() {
+                    warn!("视频「{}」未能下载", name);
+                } else {
+                    info!("视频「{}」保存在 {}", name, video_path);
+                }
+            }
+            info!("视频下载完毕");
+        }
+        Err(e) => error!("下载视频遇到错误：{e}"),
+    }
+}
+
 /// 收集任务执行所需的参数（下载类型和保存路径）
-fn collect_task_params() -> Vec<(Args<'static>, &'static PathBuf)> {
+fn collect_task_params() -> Vec<(Args<'static>, &'static SourceConfig)> {
     let mut params = Vec::new();
     CONFIG
         .favorite_list
@@ -65,19 +102,45 @@ fn collect_task_params() -> Vec<(Args<'static>, &'static PathBuf)> {
         .submission_list
         .iter()
         .for_each(|(upper_id, path)| params.push((Args::Submission { upper_id }, path)));
+    CONFIG
+        .bangumi_list
+        .iter()
+        .for_each(|(bangumi_item, path)| params.push((Args::Bangumi { bangumi_item }, path)));
     params
 }
 
+/// 解析 url_list 中尚未解析的链接，解析成功的结果会被缓存，解析失败的链接在本轮跳过，下一轮重新解析
+async fn resolve_url_list(bili_client: &BiliClient, resolved: &mut HashMap<&'static str, Resource>) {
+    for url in CONFIG.url_list.keys() {
+        if resolved.contains_key(url.as_str()) {
+            continue;
+        }
+        match resolve(bili_client, url).await {
+            Ok(resource) => {
+                resolved.insert(url, resource);
+            }
+            Err(e) => error!("解析链接 {url} 遇到错误：{e}，本轮跳过"),
+        }
+    }
+}
+
 /// 启动周期下载的任务
 fn spawn_periodic_task(
     bili_client: BiliClient,
-    params: Vec<(Args<'static>, &'static PathBuf)>,
+    params: Vec<(Args<'static>, &'static SourceConfig)>,
     connection: DatabaseConnection,
 ) -> tokio::task::JoinHandle<()> {
     let mut anchor = chrono::Local::now().date_naive();
+    // 命令行中指定的全量扫描仅对启动后的第一轮生效
+    let mut full_rescan = ARGS.full_rescan;
+    let mut resolved = HashMap::new();
     tokio::spawn(async move {
         loop {
             'inner: {
+                if !(CONFIG.schedule.scan_allowed() || CONFIG.schedule.download_allowed()) {
+                    info!("当前不在允许扫描或下载的时间段内，等待下一轮执行");
+                    break 'inner;
+                }
                 match bili_client.wbi_img().await.map(|wbi_img| wbi_img.into()) {
                     Ok(Some(mixin_key)) => bilibili::set_global_mixin_key(mixin_key),
                     Ok(_) => {
@@ -97,10 +160,67 @@ fn spawn_periodic_task(
                     anchor = chrono::Local::now().date_naive();
                 }
                 for (args, path) in &params {
-                    if let Err(e) = process_video_list(*args, &bili_client, path, &connection).await {
+                    if let Err(e) = process_video_list(*args, &bili_client, path, full_rescan, &connection).await {
+                        error!("处理过程遇到错误：{e}");
+                    }
+                }
+                resolve_url_list(&bili_client, &mut resolved).await;
+                for (url, path) in &CONFIG.url_list {
+                    let Some(resource) = resolved.get(url.as_str()) else {
+                        continue;
+                    };
+                    if let Err(e) =
+                        process_video_list(resource.into(), &bili_client, path, full_rescan, &connection).await
+                    {
                         error!("处理过程遇到错误：{e}");
                     }
                 }
+                for (mid, path) in &CONFIG.favorite_user_list {
+                    match refresh_user_favorite_list(&bili_client, mid, path).await {
+                        Ok(favorites) => {
+                            for (fid, path) in &favorites {
+                                let args = Args::Favorite { fid };
+                                if let Err(e) =
+                                    process_video_list(args, &bili_client, path, full_rescan, &connection).await
+                                {
+                                    error!("处理过程遇到错误：{e}");
+                                }
+                            }
+                        }
+                        Err(e) => error!("获取用户「{mid}」的收藏夹列表遇到错误：{e}"),
+                    }
+                }
+                if CONFIG.collected.enabled {
+                    match refresh_collected_list(&bili_client).await {
+                        Ok(collected_list) => {
+                            for (resource, path) in &collected_list {
+                                if let Err(e) =
+                                    process_video_list(resource.into(), &bili_client, path, full_rescan, &connection)
+                                        .await
+                                {
+                                    error!("处理过程遇到错误：{e}");
+                                }
+                            }
+                        }
+                        Err(e) => error!("获取收藏和订阅遇到错误：{e}"),
+                    }
+                }
+                if CONFIG.following.enabled {
+                    match refresh_following_list(&bili_client, &connection).await {
+                        Ok(submissions) => {
+                            for (upper_id, path) in &submissions {
+                                let args = Args::Submission { upper_id };
+                                if let Err(e) =
+                                    process_video_list(args, &bili_client, path, full_rescan, &connection).await
+                                {
+                                    error!("处理过程遇到错误：{e}");
+                                }
+                            }
+                        }
+                        Err(e) => error!("获取关注列表遇到错误：{e}"),
+                    }
+                }
+                full_rescan = false;
                 info!("本轮任务执行完毕，等待下一轮执行");
             }
             time::sleep(time::Duration::from_secs(CONFIG.interval)).await;
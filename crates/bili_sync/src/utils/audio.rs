@@ -0,0 +1,105 @@
+use core::str;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use tokio::fs;
+
+/// 写入音频文件的标签
+pub struct AudioTags<'a> {
+    /// 作为封面嵌入的图片，由其它子任务下载，不存在时忽略
+    pub cover: &'a Path,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub track: i32,
+    pub date: String,
+}
+
+impl AudioTags<'_> {
+    /// 使用 FFmpeg 从 input_path 中提取第一个音频流，与标签和封面一起写入 output_path，容器格式由 output_path 的扩展名决定
+    pub async fn write(&self, input_path: &Path, output_path: &Path) -> Result<()> {
+        let cover = fs::try_exists(self.cover).await?.then(|| self.cover.to_path_buf());
+        let output = tokio::process::Command::new("ffmpeg")
+            .args(self.args(input_path, cover.as_ref(), output_path))
+            .output()
+            .await?;
+        if !output.status.success() {
+            let _ = fs::remove_file(output_path).await;
+            bail!("ffmpeg error: {}", str::from_utf8(&output.stderr).unwrap_or("unknown"));
+        }
+        Ok(())
+    }
+
+    fn args(&self, input_path: &Path, cover: Option<&PathBuf>, output_path: &Path) -> Vec<String> {
+        let mut args = vec![
+            "-v".to_string(),
+            "error".to_string(),
+            "-i".to_string(),
+            input_path.to_string_lossy().to_string(),
+        ];
+        if let Some(cover) = cover {
+            args.extend(["-i".to_string(), cover.to_string_lossy().to_string()]);
+        }
+        args.extend(["-map".to_string(), "0:a:0".to_string()]);
+        if cover.is_some() {
+            args.extend([
+                "-map".to_string(),
+                "1:v:0".to_string(),
+                "-disposition:v:0".to_string(),
+                "attached_pic".to_string(),
+            ]);
+        }
+        let format = match output_path.extension().and_then(|extension| extension.to_str()) {
+            Some("flac") => "flac",
+            _ => "mp4",
+        };
+        args.extend([
+            "-map_metadata".to_string(),
+            "-1".to_string(),
+            "-metadata".to_string(),
+            format!("title={}", self.title),
+            "-metadata".to_string(),
+            format!("artist={}", self.artist),
+            "-metadata".to_string(),
+            format!("album={}", self.album),
+            "-metadata".to_string(),
+            format!("track={}", self.track),
+            "-metadata".to_string(),
+            format!("date={}", self.date),
+            "-c".to_string(),
+            "copy".to_string(),
+            "-f".to_string(),
+            format.to_string(),
+            "-y".to_string(),
+            output_path.to_string_lossy().to_string(),
+        ]);
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_args() {
+        let tags = AudioTags {
+            cover: Path::new("/tmp/a-poster.jpg"),
+            title: "标题".to_string(),
+            artist: "UP 主".to_string(),
+            album: "音乐".to_string(),
+            track: 2,
+            date: "2024-05-01".to_string(),
+        };
+        let cover = PathBuf::from("/tmp/a-poster.jpg");
+        let args = tags.args(Path::new("/tmp/a.tmp_audio"), Some(&cover), Path::new("/tmp/a.flac"));
+        let find = |arg: &str| args.iter().position(|item| item == arg).unwrap();
+        assert_eq!(args.iter().filter(|item| *item == "-i").count(), 2);
+        assert_eq!(args[find("-disposition:v:0") + 1], "attached_pic");
+        assert_eq!(args[find("-f") + 1], "flac");
+        assert!(args.contains(&"track=2".to_string()));
+        let args = tags.args(Path::new("/tmp/a.tmp_audio"), None, Path::new("/tmp/a.m4a"));
+        assert!(!args.contains(&"1:v:0".to_string()));
+        assert_eq!(args[args.iter().position(|item| item == "-f").unwrap() + 1], "mp4");
+    }
+}
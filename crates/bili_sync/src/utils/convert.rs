@@ -2,18 +2,58 @@ use chrono::{DateTime, NaiveDateTime, Utc};
 use sea_orm::ActiveValue::{NotSet, Set};
 use sea_orm::IntoActiveModel;
 
-use crate::bilibili::{PageInfo, VideoInfo};
+use crate::bilibili::{AudioQuality, PageInfo, StreamQuality, VideoInfo, VideoQuality};
 
 impl VideoInfo {
     /// 在检测视频更新时，通过该方法将 VideoInfo 转换为简单的 ActiveModel，此处仅填充一些简单信息，后续会使用详情覆盖
-    pub fn into_simple_model(self) -> bili_sync_entity::video::ActiveModel {
+    /// 同时返回视频与视频列表的关联，其中记录了视频被加入视频列表的时间
+    pub fn into_simple_model(
+        self,
+    ) -> (
+        bili_sync_entity::video::ActiveModel,
+        bili_sync_entity::video_source::ActiveModel,
+    ) {
+        let favtime = match &self {
+            VideoInfo::Favorite { fav_time, .. } | VideoInfo::WatchLater { fav_time, .. } => fav_time.naive_utc(),
+            _ => NaiveDateTime::default(),
+        };
+        let source_model = bili_sync_entity::video_source::ActiveModel {
+            path: Set(String::new()),
+            favtime: Set(favtime),
+            download_status: Set(0),
+            ..Default::default()
+        };
         let default = bili_sync_entity::video::ActiveModel {
             id: NotSet,
             created_at: NotSet,
             // 此处不使用 ActiveModel::default() 是为了让其它字段有默认值
             ..bili_sync_entity::video::Model::default().into_active_model()
         };
-        match self {
+        let video_model = match self {
+            VideoInfo::Detail {
+                title,
+                bvid,
+                intro,
+                cover,
+                upper,
+                ctime,
+                pubtime,
+                state,
+                ..
+            } => bili_sync_entity::video::ActiveModel {
+                bvid: Set(bvid),
+                name: Set(title),
+                category: Set(2), // 通过视频详情接口获取的内容类型肯定是视频
+                intro: Set(intro),
+                cover: Set(cover),
+                ctime: Set(ctime.naive_utc()),
+                pubtime: Set(pubtime.naive_utc()),
+                valid: Set(state == 0),
+                upper_id: Set(upper.mid),
+                upper_name: Set(upper.name),
+                upper_face: Set(upper.face),
+                ..default
+            },
             VideoInfo::Collection {
                 bvid,
                 cover,
@@ -36,9 +76,9 @@ impl VideoInfo {
                 cover,
                 upper,
                 ctime,
-                fav_time,
                 pubtime,
                 attr,
+                ..
             } => bili_sync_entity::video::ActiveModel {
                 bvid: Set(bvid),
                 name: Set(title),
@@ -47,8 +87,6 @@ impl VideoInfo {
                 cover: Set(cover),
                 ctime: Set(ctime.naive_utc()),
                 pubtime: Set(pubtime.naive_utc()),
-                favtime: Set(fav_time.naive_utc()),
-                download_status: Set(0),
                 valid: Set(attr == 0),
                 upper_id: Set(upper.mid),
                 upper_name: Set(upper.name),
@@ -62,9 +100,9 @@ impl VideoInfo {
                 cover,
                 upper,
                 ctime,
-                fav_time,
                 pubtime,
                 state,
+                ..
             } => bili_sync_entity::video::ActiveModel {
                 bvid: Set(bvid),
                 name: Set(title),
@@ -73,8 +111,6 @@ impl VideoInfo {
                 cover: Set(cover),
                 ctime: Set(ctime.naive_utc()),
                 pubtime: Set(pubtime.naive_utc()),
-                favtime: Set(fav_time.naive_utc()),
-                download_status: Set(0),
                 valid: Set(state == 0),
                 upper_id: Set(upper.mid),
                 upper_name: Set(upper.name),
@@ -97,12 +133,28 @@ impl VideoInfo {
                 valid: Set(true),
                 ..default
             },
-            _ => unreachable!(),
-        }
+            VideoInfo::Bangumi {
+                title,
+                bvid,
+                ep_id,
+                cover,
+                pubtime,
+            } => bili_sync_entity::video::ActiveModel {
+                bvid: Set(bvid),
+                name: Set(title),
+                cover: Set(cover),
+                ctime: Set(pubtime.naive_utc()),
+                pubtime: Set(pubtime.naive_utc()),
+                category: Set(2), // 番剧的剧集按照普通视频处理
+                valid: Set(true),
+                ep_id: Set(Some(ep_id)),
+                ..default
+            },
+        };
+        (video_model, source_model)
     }
 
     /// 填充视频详情时调用，该方法会将视频详情附加到原有的 Model 上
-    /// 特殊地，如果在检测视频更新时记录了 favtime，那么 favtime 会维持原样，否则会使用 pubtime 填充
     pub fn into_detail_model(self, base_model: bili_sync_entity::video::Model) -> bili_sync_entity::video::ActiveModel {
         match self {
             VideoInfo::Detail {
@@ -123,12 +175,6 @@ impl VideoInfo {
                 cover: Set(cover),
                 ctime: Set(ctime.naive_utc()),
                 pubtime: Set(pubtime.naive_utc()),
-                favtime: if base_model.favtime != NaiveDateTime::default() {
-                    NotSet // 之前设置了 favtime，不覆盖
-                } else {
-                    Set(pubtime.naive_utc()) // 未设置过 favtime，使用 pubtime 填充
-                },
-                download_status: Set(0),
                 valid: Set(state == 0),
                 upper_id: Set(upper.mid),
                 upper_name: Set(upper.name),
@@ -139,14 +185,59 @@ impl VideoInfo {
         }
     }
 
+    /// 刷新元数据时调用，仅更新视频与分页的描述信息，不会改变下载状态
+    pub fn apply_metadata(
+        self,
+        video_model: &mut bili_sync_entity::video::Model,
+        page_models: &mut [bili_sync_entity::page::Model],
+    ) {
+        let VideoInfo::Detail {
+            title,
+            intro,
+            cover,
+            upper,
+            ctime,
+            pubtime,
+            pages,
+            ..
+        } = self
+        else {
+            unreachable!()
+        };
+        video_model.name = title;
+        video_model.intro = intro;
+        video_model.cover = cover;
+        video_model.upper_name = upper.name;
+        video_model.upper_face = upper.face;
+        video_model.ctime = ctime.naive_utc();
+        video_model.pubtime = pubtime.naive_utc();
+        for page_model in page_models.iter_mut() {
+            if let Some(page) = pages.iter().find(|page| page.page == page_model.pid) {
+                page_model.name.clone_from(&page.name);
+            }
+        }
+    }
+
+    pub fn bvid(&self) -> &str {
+        match self {
+            VideoInfo::Detail { bvid, .. }
+            | VideoInfo::Collection { bvid, .. }
+            | VideoInfo::Favorite { bvid, .. }
+            | VideoInfo::WatchLater { bvid, .. }
+            | VideoInfo::Submission { bvid, .. }
+            | VideoInfo::Bangumi { bvid, .. } => bvid,
+        }
+    }
+
     /// 获取视频的发布时间，用于对时间做筛选检查新视频
     pub fn release_datetime(&self) -> &DateTime<Utc> {
         match self {
-            VideoInfo::Collection { pubtime: time, .. }
+            VideoInfo::Detail { pubtime: time, .. }
+            | VideoInfo::Collection { pubtime: time, .. }
             | VideoInfo::Favorite { fav_time: time, .. }
             | VideoInfo::WatchLater { fav_time: time, .. }
-            | VideoInfo::Submission { ctime: time, .. } => time,
-            _ => unreachable!(),
+            | VideoInfo::Submission { ctime: time, .. }
+            | VideoInfo::Bangumi { pubtime: time, .. } => time,
         }
     }
 }
@@ -154,7 +245,7 @@ impl VideoInfo {
 impl PageInfo {
     pub fn into_active_model(
         self,
-        video_model: &bili_sync_entity::video::Model,
+        source_model: &bili_sync_entity::video_source::Model,
     ) -> bili_sync_entity::page::ActiveModel {
         let (width, height) = match &self.dimension {
             Some(d) => {
@@ -167,7 +258,7 @@ impl PageInfo {
             None => (None, None),
         };
         bili_sync_entity::page::ActiveModel {
-            video_id: Set(video_model.id),
+            video_source_id: Set(source_model.id),
             cid: Set(self.cid),
             pid: Set(self.page),
             name: Set(self.name),
@@ -180,3 +271,31 @@ impl PageInfo {
         }
     }
 }
+
+impl From<&bili_sync_entity::page::Model> for StreamQuality {
+    fn from(page_model: &bili_sync_entity::page::Model) -> Self {
+        Self {
+            video_quality: page_model
+                .video_quality
+                .and_then(|quality| VideoQuality::from_repr(quality as _)),
+            video_codec: page_model.video_codec.as_deref().and_then(|codec| codec.parse().ok()),
+            audio_quality: page_model
+                .audio_quality
+                .and_then(|quality| AudioQuality::from_repr(quality as _)),
+        }
+    }
+}
+
+impl StreamQuality {
+    /// 是否记录了下载的视频流，混合流与尚未记录的分页均无法比较
+    pub fn is_recorded(&self) -> bool {
+        self.video_quality.is_some() || self.audio_quality.is_some()
+    }
+
+    /// 将视频流的信息写入分页的 ActiveModel
+    pub fn apply_to(&self, page_model: &mut bili_sync_entity::page::ActiveModel) {
+        page_model.video_quality = Set(self.video_quality.map(|quality| quality as i32));
+        page_model.video_codec = Set(self.video_codec.as_ref().map(|codec| codec.as_ref().to_owned()));
+        page_model.audio_quality = Set(self.audio_quality.map(|quality| quality as i32));
+    }
+}
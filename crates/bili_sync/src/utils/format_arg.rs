@@ -1,20 +1,32 @@
 use serde_json::json;
 
+use crate::bilibili::FavoriteFolder;
 use crate::config::CONFIG;
 
-pub fn video_format_args(video_model: &bili_sync_entity::video::Model) -> serde_json::Value {
+pub fn favorite_format_args(folder: &FavoriteFolder) -> serde_json::Value {
+    json!({
+        "fid": folder.id,
+        "title": &folder.title,
+    })
+}
+
+pub fn video_format_args(
+    video_model: &bili_sync_entity::video::Model,
+    source_model: &bili_sync_entity::video_source::Model,
+) -> serde_json::Value {
     json!({
         "bvid": &video_model.bvid,
         "title": &video_model.name,
         "upper_name": &video_model.upper_name,
         "upper_mid": &video_model.upper_id,
         "pubtime": &video_model.pubtime.and_utc().format(&CONFIG.time_format).to_string(),
-        "fav_time": &video_model.favtime.and_utc().format(&CONFIG.time_format).to_string(),
+        "fav_time": &source_model.favtime.and_utc().format(&CONFIG.time_format).to_string(),
     })
 }
 
 pub fn page_format_args(
     video_model: &bili_sync_entity::video::Model,
+    source_model: &bili_sync_entity::video_source::Model,
     page_model: &bili_sync_entity::page::Model,
 ) -> serde_json::Value {
     json!({
@@ -25,6 +37,6 @@ pub fn page_format_args(
         "ptitle": &page_model.name,
         "pid": page_model.pid,
         "pubtime": video_model.pubtime.and_utc().format(&CONFIG.time_format).to_string(),
-        "fav_time": video_model.favtime.and_utc().format(&CONFIG.time_format).to_string(),
+        "fav_time": source_model.favtime.and_utc().format(&CONFIG.time_format).to_string(),
     })
 }
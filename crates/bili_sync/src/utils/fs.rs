@@ -0,0 +1,253 @@
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+
+use anyhow::{bail, Result};
+use bili_sync_entity::*;
+use tokio::fs;
+
+use crate::config::DedupeMode;
+
+/// 获取视频在 dir 下对应的所有文件
+/// 多页视频独占整个文件夹，直接返回文件夹本身；单页视频可能与其它视频共用文件夹，仅返回以分页文件名命名的文件；
+/// 番剧的所有剧集共用以剧集命名的文件夹，仅返回 Season 1 中属于该集的文件
+pub async fn video_files(dir: &Path, video_model: &video::Model, pages: &[page::Model]) -> Result<Vec<PathBuf>> {
+    if !fs::try_exists(dir).await? {
+        return Ok(Vec::new());
+    }
+    let dir = if video_model.single_page.unwrap_or_default() {
+        dir.to_path_buf()
+    } else if video_model.ep_id.is_some() {
+        dir.join("Season 1")
+    } else {
+        return Ok(vec![dir.to_path_buf()]);
+    };
+    if !fs::try_exists(&dir).await? {
+        return Ok(Vec::new());
+    }
+    let base_names = pages
+        .iter()
+        .filter_map(|page| Path::new(page.path.as_deref()?).file_stem())
+        .map(|stem| stem.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if base_names
+            .iter()
+            .any(|base_name| page_file_suffix(&file_name, base_name).is_some())
+        {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+/// 判断文件是否为下载分页时以 base_name 命名的文件，是则返回文件名中 base_name 之后的部分
+/// 共用文件夹中可能存在以 base_name 开头的其它视频的文件（例如 `Foo. Bar.mp4` 之于 `Foo`），因此仅匹配 download_page 中写入的文件名
+pub fn page_file_suffix<'a>(file_name: &'a str, base_name: &str) -> Option<&'a str> {
+    let suffix = file_name.strip_prefix(base_name)?;
+    if ["-poster.jpg", "-fanart.jpg", "-thumb.jpg"].contains(&suffix) {
+        return Some(suffix);
+    }
+    let extension = suffix.strip_prefix('.')?;
+    // 下载中断时遗留的 `.part` 文件同样属于该分页
+    let extension = match extension.find(".part") {
+        Some(idx)
+            if matches!(&extension[idx + 5..], "" | ".validator")
+                || extension[idx + 5..].bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            &extension[..idx]
+        }
+        _ => extension,
+    };
+    let known = [
+        "mp4",
+        "mkv",
+        "m4a",
+        "flac",
+        "nfo",
+        "zh-CN.default.ass",
+        "tmp_video",
+        "tmp_audio",
+    ]
+    .contains(&extension)
+        || extension.strip_suffix(".srt").is_some_and(|lan| {
+            !lan.is_empty() && lan.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        });
+    known.then_some(suffix)
+}
+
+/// 移动文件或文件夹，目标路径位于其它文件系统时回退到复制后删除
+pub fn move_path<'a>(from: &'a Path, to: &'a Path) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+    Box::pin(async move {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        if fs::rename(from, to).await.is_ok() {
+            return Ok(());
+        }
+        if fs::metadata(from).await?.is_dir() {
+            fs::create_dir_all(to).await?;
+            let mut entries = fs::read_dir(from).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                move_path(&entry.path(), &to.join(entry.file_name())).await?;
+            }
+            fs::remove_dir(from).await?;
+        } else {
+            fs::copy(from, to).await?;
+            fs::remove_file(from).await?;
+        }
+        Ok(())
+    })
+}
+
+/// 以指定的方式将已有的文件链接到新的路径，目标路径已存在时会被覆盖
+pub async fn link_file(from: &Path, to: &Path, mode: DedupeMode) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    unlink_file(to).await?;
+    match mode {
+        DedupeMode::None => bail!("dedupe is disabled"),
+        DedupeMode::Hardlink => fs::hard_link(from, to).await?,
+        #[cfg(target_family = "unix")]
+        DedupeMode::Symlink => fs::symlink(from, to).await?,
+        #[cfg(target_family = "windows")]
+        DedupeMode::Symlink => fs::symlink_file(from, to).await?,
+        DedupeMode::Reflink => {
+            // 标准库没有提供 reflink 的接口，借助系统的 cp 命令实现
+            #[cfg(target_os = "macos")]
+            let arg = "-c";
+            #[cfg(not(target_os = "macos"))]
+            let arg = "--reflink=always";
+            let output = tokio::process::Command::new("cp")
+                .arg(arg)
+                .arg(from)
+                .arg(to)
+                .output()
+                .await?;
+            if !output.status.success() {
+                bail!("cp error: {}", String::from_utf8_lossy(&output.stderr));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 获取文件或文件夹的总大小
+pub fn path_size(path: &Path) -> Pin<Box<dyn Future<Output = Result<u64>> + Send + '_>> {
+    Box::pin(async move {
+        let metadata = fs::metadata(path).await?;
+        if !metadata.is_dir() {
+            return Ok(metadata.len());
+        }
+        let mut size = 0;
+        let mut entries = fs::read_dir(path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            size += path_size(&entry.path()).await?;
+        }
+        Ok(size)
+    })
+}
+
+/// 删除已经存在的文件，写入前调用以避免修改硬链接或符号链接指向的其它文件
+pub async fn unlink_file(path: &Path) -> Result<()> {
+    if fs::symlink_metadata(path).await.is_ok() {
+        fs::remove_file(path).await?;
+    }
+    Ok(())
+}
+
+/// 删除文件或文件夹
+pub async fn remove_path(path: &Path) -> Result<()> {
+    if fs::metadata(path).await?.is_dir() {
+        fs::remove_dir_all(path).await?;
+    } else {
+        fs::remove_file(path).await?;
+    }
+    Ok(())
+}
+
+/// 文件夹为空时将其删除
+pub async fn remove_dir_if_empty(dir: &Path) -> Result<()> {
+    if fs::try_exists(dir).await? && fs::read_dir(dir).await?.next_entry().await?.is_none() {
+        fs::remove_dir(dir).await?;
+    }
+    Ok(())
+}
+
+/// 移出视频的文件后清理视频的文件夹，番剧剧集的文件位于共用文件夹的 Season 1 中，需要先清理
+pub async fn remove_video_dir_if_empty(dir: &Path) -> Result<()> {
+    remove_dir_if_empty(&dir.join("Season 1")).await?;
+    remove_dir_if_empty(dir).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_file_suffix() {
+        for (file_name, suffix) in [
+            ("Foo.mp4", Some(".mp4")),
+            ("Foo.mkv", Some(".mkv")),
+            ("Foo.flac", Some(".flac")),
+            ("Foo.nfo", Some(".nfo")),
+            ("Foo-poster.jpg", Some("-poster.jpg")),
+            ("Foo-fanart.jpg", Some("-fanart.jpg")),
+            ("Foo.zh-CN.default.ass", Some(".zh-CN.default.ass")),
+            ("Foo.en-US.srt", Some(".en-US.srt")),
+            ("Foo.mp4.part", Some(".mp4.part")),
+            ("Foo.tmp_video.part2", Some(".tmp_video.part2")),
+            // 其它视频的文件
+            ("Foo. Bar.mp4", None),
+            ("Foo.Bar.mp4", None),
+            ("Foo 2.mp4", None),
+            ("Foo.txt", None),
+            ("Bar.mp4", None),
+        ] {
+            assert_eq!(page_file_suffix(file_name, "Foo"), suffix, "{}", file_name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_episode_files() {
+        let dir = std::env::temp_dir().join(format!("bili-sync-episode-{}", std::process::id()));
+        let season = dir.join("Season 1");
+        std::fs::create_dir_all(&season).unwrap();
+        for file in [
+            "番剧 - S01E01.mp4",
+            "番剧 - S01E01.nfo",
+            "番剧 - S01E01-thumb.jpg",
+            "番剧 - S01E02.mp4",
+            "番剧 - S01E02.nfo",
+        ] {
+            std::fs::write(season.join(file), b"").unwrap();
+        }
+        std::fs::write(dir.join("tvshow.nfo"), b"").unwrap();
+        let video_model = video::Model {
+            single_page: Some(false),
+            ep_id: Some(101),
+            ..Default::default()
+        };
+        let page_model = page::Model {
+            path: Some(season.join("番剧 - S01E01.mp4").to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let mut files = video_files(&dir, &video_model, &[page_model]).await.unwrap();
+        files.sort();
+        // 仅包含该集的文件，不包含共用的文件夹与其它剧集
+        assert_eq!(
+            files,
+            ["番剧 - S01E01-thumb.jpg", "番剧 - S01E01.mp4", "番剧 - S01E01.nfo"].map(|file| season.join(file))
+        );
+        for file in &files {
+            remove_path(file).await.unwrap();
+        }
+        remove_video_dir_if_empty(&dir).await.unwrap();
+        assert!(season.join("番剧 - S01E02.mp4").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
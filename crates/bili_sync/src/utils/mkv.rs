@@ -0,0 +1,279 @@
+use core::str;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use tokio::fs;
+
+use crate::bilibili::Chapter;
+
+/// 输出为 MKV 时需要嵌入的文件，这些文件由其它子任务下载，不存在时忽略
+pub struct MkvSources<'a> {
+    pub cover: &'a Path,
+    pub danmaku: &'a Path,
+    /// 字幕文件路径的模板，实际的字幕文件为 {stem}.{lan}.srt
+    pub subtitle: &'a Path,
+    pub title: String,
+    pub artist: String,
+    pub date: String,
+}
+
+impl MkvSources<'_> {
+    /// 检查所有文件是否存在，与章节一起组成合并 MKV 需要的内容
+    pub async fn collect(self, chapters: Vec<Chapter>) -> Result<MkvInput> {
+        let mut subtitles = Vec::new();
+        if let (Some(dir), Some(stem)) = (self.subtitle.parent(), self.subtitle.file_stem()) {
+            let prefix = format!("{}.", stem.to_string_lossy());
+            if fs::try_exists(dir).await? {
+                let mut entries = fs::read_dir(dir).await?;
+                while let Some(entry) = entries.next_entry().await? {
+                    let file_name = entry.file_name().to_string_lossy().to_string();
+                    // 与 fetch_page_subtitle 中的命名保持一致
+                    if let Some(lan) = file_name
+                        .strip_prefix(&prefix)
+                        .and_then(|rest| rest.strip_suffix(".srt"))
+                        .filter(|lan| !lan.is_empty() && !lan.contains('.'))
+                    {
+                        subtitles.push((lan.to_string(), entry.path()));
+                    }
+                }
+            }
+        }
+        subtitles.sort();
+        Ok(MkvInput {
+            subtitles,
+            danmaku: fs::try_exists(self.danmaku).await?.then(|| self.danmaku.to_path_buf()),
+            cover: fs::try_exists(self.cover).await?.then(|| self.cover.to_path_buf()),
+            chapters,
+            title: self.title,
+            artist: self.artist,
+            date: self.date,
+        })
+    }
+}
+
+/// 合并为 MKV 文件的视频流以外的内容
+pub struct MkvInput {
+    /// 字幕的语言与 srt 文件路径
+    pub subtitles: Vec<(String, PathBuf)>,
+    /// 弹幕的 ass 文件路径，作为非默认的字幕轨道
+    pub danmaku: Option<PathBuf>,
+    /// 封面图片，作为附件嵌入
+    pub cover: Option<PathBuf>,
+    pub chapters: Vec<Chapter>,
+    pub title: String,
+    pub artist: String,
+    pub date: String,
+}
+
+impl MkvInput {
+    /// 使用 FFmpeg 将视频流、音频流（为 None 时使用视频文件中的音频）与其它内容合并到 output_path 中
+    pub async fn mux(&self, video_path: &Path, audio_path: Option<&Path>, output_path: &Path) -> Result<()> {
+        let metadata_path = (!self.chapters.is_empty()).then(|| output_path.with_extension("ffmetadata"));
+        if let Some(metadata_path) = &metadata_path {
+            fs::write(metadata_path, ffmetadata(&self.chapters)).await?;
+        }
+        let output = tokio::process::Command::new("ffmpeg")
+            .args(self.args(video_path, audio_path, metadata_path.as_deref(), output_path))
+            .output()
+            .await;
+        if let Some(metadata_path) = &metadata_path {
+            let _ = fs::remove_file(metadata_path).await;
+        }
+        let output = output?;
+        if !output.status.success() {
+            let _ = fs::remove_file(output_path).await;
+            bail!("ffmpeg error: {}", str::from_utf8(&output.stderr).unwrap_or("unknown"));
+        }
+        Ok(())
+    }
+
+    /// 嵌入到 MKV 文件中的字幕、弹幕与封面文件
+    pub fn embedded_files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.subtitles
+            .iter()
+            .map(|(_, path)| path)
+            .chain(self.danmaku.iter())
+            .chain(self.cover.iter())
+    }
+
+    fn args(
+        &self,
+        video_path: &Path,
+        audio_path: Option<&Path>,
+        metadata_path: Option<&Path>,
+        output_path: &Path,
+    ) -> Vec<String> {
+        let mut inputs = vec![video_path];
+        inputs.extend(audio_path);
+        inputs.extend(self.subtitles.iter().map(|(_, path)| path.as_path()));
+        inputs.extend(self.danmaku.as_deref());
+        inputs.extend(metadata_path);
+        let mut args = vec!["-v".to_string(), "error".to_string()];
+        for input in &inputs {
+            args.extend(["-i".to_string(), input.to_string_lossy().to_string()]);
+        }
+        args.extend(["-map".to_string(), "0:v:0".to_string()]);
+        let first_track = match audio_path {
+            Some(_) => {
+                args.extend(["-map".to_string(), "1:a:0".to_string()]);
+                2
+            }
+            None => {
+                args.extend(["-map".to_string(), "0:a?".to_string()]);
+                1
+            }
+        };
+        let tracks = self
+            .subtitles
+            .iter()
+            .map(|(lan, _)| (language(lan), lan.as_str()))
+            .chain(self.danmaku.iter().map(|_| ("chi", "弹幕")));
+        for (idx, (language, title)) in tracks.enumerate() {
+            args.extend([
+                "-map".to_string(),
+                (first_track + idx).to_string(),
+                format!("-metadata:s:s:{}", idx),
+                format!("language={}", language),
+                format!("-metadata:s:s:{}", idx),
+                format!("title={}", title),
+                format!("-disposition:s:{}", idx),
+                // 仅第一个字幕作为默认轨道，弹幕始终不是默认轨道
+                if idx == 0 && !self.subtitles.is_empty() {
+                    "default".to_string()
+                } else {
+                    "0".to_string()
+                },
+            ]);
+        }
+        // 不继承视频文件中原有的元数据，章节从最后一个输入的元数据文件中读取
+        let chapters = match metadata_path {
+            Some(_) => (inputs.len() - 1).to_string(),
+            None => "-1".to_string(),
+        };
+        args.extend([
+            "-map_metadata".to_string(),
+            "-1".to_string(),
+            "-map_chapters".to_string(),
+            chapters,
+            "-metadata".to_string(),
+            format!("title={}", self.title),
+            "-metadata".to_string(),
+            format!("artist={}", self.artist),
+            "-metadata".to_string(),
+            format!("date={}", self.date),
+        ]);
+        if let Some(cover) = &self.cover {
+            args.extend([
+                "-attach".to_string(),
+                cover.to_string_lossy().to_string(),
+                "-metadata:s:t".to_string(),
+                "mimetype=image/jpeg".to_string(),
+                "-metadata:s:t".to_string(),
+                "filename=cover.jpg".to_string(),
+            ]);
+        }
+        args.extend(["-c", "copy", "-f", "matroska", "-y"].map(String::from));
+        args.push(output_path.to_string_lossy().to_string());
+        args
+    }
+}
+
+/// 将字幕的语言转换为 Matroska 使用的 ISO 639-2 语言代码
+fn language(lan: &str) -> &'static str {
+    let lan = lan.strip_prefix("ai-").unwrap_or(lan);
+    match lan.split('-').next().unwrap_or_default() {
+        "zh" => "chi",
+        "en" => "eng",
+        "ja" => "jpn",
+        "ko" => "kor",
+        "es" => "spa",
+        "fr" => "fre",
+        "de" => "ger",
+        "ru" => "rus",
+        "pt" => "por",
+        "ar" => "ara",
+        _ => "und",
+    }
+}
+
+/// 生成包含章节信息的 FFmpeg 元数据文件
+fn ffmetadata(chapters: &[Chapter]) -> String {
+    let escape = |value: &str| {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    };
+    let mut content = String::from(";FFMETADATA1\n");
+    for chapter in chapters {
+        content.push_str(&format!(
+            "[CHAPTER]\nTIMEBASE=1/1\nSTART={}\nEND={}\ntitle={}\n",
+            chapter.from,
+            chapter.to,
+            escape(&chapter.content)
+        ));
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mkv_args() {
+        let input = MkvInput {
+            subtitles: vec![("zh-CN".to_string(), PathBuf::from("/tmp/a.zh-CN.srt"))],
+            danmaku: Some(PathBuf::from("/tmp/a.zh-CN.default.ass")),
+            cover: Some(PathBuf::from("/tmp/a-poster.jpg")),
+            chapters: Vec::new(),
+            title: "标题".to_string(),
+            artist: "UP 主".to_string(),
+            date: "2024-05-01".to_string(),
+        };
+        let args = input.args(
+            Path::new("/tmp/video"),
+            Some(Path::new("/tmp/audio")),
+            Some(Path::new("/tmp/a.ffmetadata")),
+            Path::new("/tmp/a.mkv"),
+        );
+        let find = |arg: &str| args.iter().position(|item| item == arg).unwrap();
+        assert_eq!(args.iter().filter(|item| *item == "-i").count(), 5);
+        assert_eq!(args[find("1:a:0") - 1], "-map");
+        // 字幕为默认轨道，弹幕不是
+        assert_eq!(args[find("language=chi") - 1], "-metadata:s:s:0");
+        assert_eq!(args[find("-disposition:s:0") + 1], "default");
+        assert_eq!(args[find("-disposition:s:1") + 1], "0");
+        assert_eq!(args[find("title=弹幕") - 1], "-metadata:s:s:1");
+        assert_eq!(args[find("-map_chapters") + 1], "4");
+        assert_eq!(args[find("-attach") + 1], "/tmp/a-poster.jpg");
+        assert_eq!(args.last().unwrap(), "/tmp/a.mkv");
+    }
+
+    #[test]
+    fn test_ffmetadata() {
+        let chapters = vec![
+            Chapter {
+                from: 0,
+                to: 30,
+                content: "开场".to_string(),
+            },
+            Chapter {
+                from: 30,
+                to: 95,
+                content: "a=b;c".to_string(),
+            },
+        ];
+        assert_eq!(
+            ffmetadata(&chapters),
+            ";FFMETADATA1\n[CHAPTER]\nTIMEBASE=1/1\nSTART=0\nEND=30\ntitle=开场\n[CHAPTER]\nTIMEBASE=1/1\nSTART=30\nEND=95\ntitle=a\\=b\\;c\n"
+        );
+        assert_eq!(language("zh-Hans"), "chi");
+        assert_eq!(language("ai-en"), "eng");
+        assert_eq!(language("xx"), "und");
+    }
+}
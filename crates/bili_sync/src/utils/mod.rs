@@ -1,8 +1,13 @@
+pub mod audio;
 pub mod convert;
 pub mod filenamify;
 pub mod format_arg;
+pub mod fs;
+pub mod mkv;
 pub mod model;
 pub mod nfo;
+pub mod probe;
+pub mod remux;
 pub mod status;
 
 use tracing_subscriber::util::SubscriberInitExt;
@@ -1,69 +1,289 @@
+use std::collections::HashMap;
+
 use anyhow::{Context, Result};
 use bili_sync_entity::*;
 use sea_orm::entity::prelude::*;
-use sea_orm::sea_query::{OnConflict, SimpleExpr};
-use sea_orm::DatabaseTransaction;
+use sea_orm::sea_query::{OnConflict, Query, SelectStatement, SimpleExpr};
+use sea_orm::ActiveValue::Set;
+use sea_orm::{DatabaseTransaction, IntoActiveModel, LoaderTrait};
 
 use crate::adapter::{VideoListModel, VideoListModelEnum};
 use crate::bilibili::{PageInfo, VideoInfo};
-use crate::utils::status::STATUS_COMPLETED;
+use crate::config::ContentFilter;
+use crate::utils::status::{PageStatus, STATUS_COMPLETED};
+
+/// 已经创建了分页的视频来源关联的 id
+fn sources_with_pages() -> SelectStatement {
+    Query::select()
+        .column(page::Column::VideoSourceId)
+        .from(page::Entity)
+        .to_owned()
+}
 
-/// 筛选未填充的视频
+/// 筛选未填充的视频，视频的详情由所有视频来源共享，因此此处以关联是否已经创建了分页为准
 pub async fn filter_unfilled_videos(
     additional_expr: SimpleExpr,
     conn: &DatabaseConnection,
-) -> Result<Vec<video::Model>> {
-    video::Entity::find()
+) -> Result<Vec<(video_source::Model, video::Model)>> {
+    let videos = video_source::Entity::find()
+        .find_also_related(video::Entity)
         .filter(
             video::Column::Valid
                 .eq(true)
-                .and(video::Column::DownloadStatus.eq(0))
+                .and(video_source::Column::DownloadStatus.eq(0))
                 .and(video::Column::Category.eq(2))
-                .and(video::Column::SinglePage.is_null())
+                .and(video_source::Column::Id.not_in_subquery(sources_with_pages()))
+                .and(video_source::Column::RemovedAt.is_null())
+                .and(video_source::Column::ExpiredAt.is_null())
+                .and(video_source::Column::FilteredReason.is_null())
                 .and(additional_expr),
         )
         .all(conn)
         .await
-        .context("filter unfilled videos failed")
+        .context("filter unfilled videos failed")?;
+    Ok(videos
+        .into_iter()
+        .filter_map(|(source_model, video_model)| Some((source_model, video_model?)))
+        .collect())
+}
+
+/// 筛选视频来源中的视频，同时获取视频的元数据与该视频来源下的分页
+pub async fn filter_source_video_pages(
+    additional_expr: SimpleExpr,
+    connection: &DatabaseConnection,
+) -> Result<Vec<(video_source::Model, video::Model, Vec<page::Model>)>> {
+    let (source_models, video_models): (Vec<_>, Vec<_>) = video_source::Entity::find()
+        .find_also_related(video::Entity)
+        .filter(additional_expr)
+        .all(connection)
+        .await?
+        .into_iter()
+        .filter_map(|(source_model, video_model)| Some((source_model, video_model?)))
+        .unzip();
+    let pages = source_models.load_many(page::Entity, connection).await?;
+    Ok(source_models
+        .into_iter()
+        .zip(video_models)
+        .zip(pages)
+        .map(|((source_model, video_model), pages)| (source_model, video_model, pages))
+        .collect())
 }
 
 /// 筛选未处理完成的视频和视频页
 pub async fn filter_unhandled_video_pages(
     additional_expr: SimpleExpr,
     connection: &DatabaseConnection,
-) -> Result<Vec<(video::Model, Vec<page::Model>)>> {
+) -> Result<Vec<(video_source::Model, video::Model, Vec<page::Model>)>> {
+    filter_source_video_pages(
+        video::Column::Valid
+            .eq(true)
+            .and(video_source::Column::DownloadStatus.lt(STATUS_COMPLETED))
+            .and(video::Column::Category.eq(2))
+            .and(video::Column::SinglePage.is_not_null())
+            .and(video_source::Column::Id.in_subquery(sources_with_pages()))
+            .and(video_source::Column::RemovedAt.is_null())
+            .and(video_source::Column::ExpiredAt.is_null())
+            .and(video_source::Column::FilteredReason.is_null())
+            .and(additional_expr),
+        connection,
+    )
+    .await
+    .context("filter unhandled video pages failed")
+}
+
+/// 筛选需要检查是否新增分页的视频，仅包含发布时间晚于 published_after 且上次检查早于 checked_before 的视频
+pub async fn filter_recheck_videos(
+    additional_expr: SimpleExpr,
+    published_after: DateTime,
+    checked_before: DateTime,
+    connection: &DatabaseConnection,
+) -> Result<Vec<video::Model>> {
     video::Entity::find()
+        .inner_join(video_source::Entity)
         .filter(
             video::Column::Valid
                 .eq(true)
-                .and(video::Column::DownloadStatus.lt(STATUS_COMPLETED))
                 .and(video::Column::Category.eq(2))
                 .and(video::Column::SinglePage.is_not_null())
+                .and(video::Column::EpId.is_null())
+                .and(video_source::Column::RemovedAt.is_null())
+                .and(video_source::Column::ExpiredAt.is_null())
+                .and(video_source::Column::FilteredReason.is_null())
+                .and(video::Column::Pubtime.gt(published_after))
+                .and(
+                    video::Column::PagesCheckedAt
+                        .is_null()
+                        .or(video::Column::PagesCheckedAt.lt(checked_before)),
+                )
                 .and(additional_expr),
         )
+        .all(connection)
+        .await
+        .context("filter recheck videos failed")
+}
+
+/// 筛选需要刷新元数据的视频，仅包含已经开始下载且上次刷新早于 checked_before 的视频
+pub async fn filter_refresh_videos(
+    additional_expr: SimpleExpr,
+    checked_before: DateTime,
+    connection: &DatabaseConnection,
+) -> Result<Vec<video::Model>> {
+    video::Entity::find()
+        .inner_join(video_source::Entity)
+        .filter(
+            video::Column::Valid
+                .eq(true)
+                .and(video::Column::Category.eq(2))
+                .and(video::Column::SinglePage.is_not_null())
+                .and(video::Column::EpId.is_null())
+                .and(video_source::Column::RemovedAt.is_null())
+                .and(video_source::Column::ExpiredAt.is_null())
+                .and(video_source::Column::FilteredReason.is_null())
+                .and(video_source::Column::Path.ne(""))
+                .and(
+                    video::Column::MetadataCheckedAt
+                        .is_null()
+                        .or(video::Column::MetadataCheckedAt.lt(checked_before)),
+                )
+                .and(additional_expr),
+        )
+        .all(connection)
+        .await
+        .context("filter refresh videos failed")
+}
+
+/// 筛选需要检查是否有更好视频流的分页，仅包含发布时间晚于 published_after 的视频中，已经下载完成、记录了视频流且上次检查早于 checked_before 的分页
+pub async fn filter_upgrade_pages(
+    additional_expr: SimpleExpr,
+    published_after: DateTime,
+    checked_before: DateTime,
+    connection: &DatabaseConnection,
+) -> Result<Vec<(video_source::Model, video::Model, Vec<page::Model>)>> {
+    let videos = filter_source_video_pages(
+        video::Column::Valid
+            .eq(true)
+            .and(video::Column::Category.eq(2))
+            .and(video::Column::SinglePage.is_not_null())
+            .and(video_source::Column::Id.in_subquery(sources_with_pages()))
+            .and(video_source::Column::RemovedAt.is_null())
+            .and(video_source::Column::ExpiredAt.is_null())
+            .and(video_source::Column::FilteredReason.is_null())
+            .and(video::Column::Pubtime.gt(published_after))
+            .and(additional_expr),
+        connection,
+    )
+    .await
+    .context("filter upgrade pages failed")?;
+    Ok(videos
+        .into_iter()
+        .filter_map(|(source_model, video_model, pages)| {
+            let pages = pages
+                .into_iter()
+                .filter(|page| {
+                    PageStatus::from(page.download_status).is_ok(1)
+                        && (page.video_quality.is_some() || page.audio_quality.is_some())
+                        && page
+                            .quality_checked_at
+                            .is_none_or(|checked_at| checked_at < checked_before)
+                })
+                .collect::<Vec<_>>();
+            (!pages.is_empty()).then_some((source_model, video_model, pages))
+        })
+        .collect())
+}
+
+/// 获取单独下载的视频中指定 bvid 的视频与其关联，关联中记录了视频实际的保存位置
+pub async fn filter_adhoc_videos(
+    bvids: &[String],
+    connection: &DatabaseConnection,
+) -> Result<Vec<(video_source::Model, video::Model)>> {
+    let videos = video_source::Entity::find()
+        .find_also_related(video::Entity)
+        .filter(
+            video_source::Column::SourceType
+                .eq(video_source::SourceType::Adhoc)
+                .and(video::Column::Bvid.is_in(bvids)),
+        )
+        .all(connection)
+        .await
+        .context("filter adhoc videos failed")?;
+    Ok(videos
+        .into_iter()
+        .filter_map(|(source_model, video_model)| Some((source_model, video_model?)))
+        .collect())
+}
+
+/// 获取视频在所有视频来源中的关联与对应的分页
+pub async fn filter_video_sources(
+    video_id: i32,
+    connection: &DatabaseConnection,
+) -> Result<Vec<(video_source::Model, Vec<page::Model>)>> {
+    video_source::Entity::find()
+        .filter(video_source::Column::VideoId.eq(video_id))
         .find_with_related(page::Entity)
         .all(connection)
         .await
-        .context("filter unhandled video pages failed")
+        .context("filter video sources failed")
 }
 
-/// 尝试创建 Video Model，如果发生冲突则忽略
+/// 获取视频在所有视频来源中的分页
+pub async fn filter_video_pages(video_id: i32, connection: &DatabaseConnection) -> Result<Vec<page::Model>> {
+    page::Entity::find()
+        .inner_join(video_source::Entity)
+        .filter(video_source::Column::VideoId.eq(video_id))
+        .all(connection)
+        .await
+        .context("filter video pages failed")
+}
+
+/// 尝试创建 Video Model 与其在视频列表中的关联，如果发生冲突则忽略
+/// 已经存在于其它视频列表中的视频不会重复创建，仅添加新的关联
 pub async fn create_videos(
     videos_info: Vec<VideoInfo>,
     video_list_model: &VideoListModelEnum,
+    content_filter: &ContentFilter,
     connection: &DatabaseConnection,
 ) -> Result<()> {
-    let video_models = videos_info
+    let (video_models, source_models): (Vec<_>, Vec<_>) =
+        videos_info.into_iter().map(VideoInfo::into_simple_model).unzip();
+    let bvids = video_models
+        .iter()
+        .map(|model| model.bvid.as_ref().clone())
+        .collect::<Vec<_>>();
+    video::Entity::insert_many(video_models)
+        .on_conflict(OnConflict::column(video::Column::Bvid).do_nothing().to_owned())
+        .do_nothing()
+        .exec(connection)
+        .await?;
+    let videos = video::Entity::find()
+        .filter(video::Column::Bvid.is_in(bvids.iter()))
+        .all(connection)
+        .await?
         .into_iter()
-        .map(|v| {
-            let mut model = v.into_simple_model();
-            video_list_model.set_relation_id(&mut model);
-            model
+        .map(|model| (model.bvid.clone(), model))
+        .collect::<HashMap<_, _>>();
+    let source_models = bvids
+        .iter()
+        .zip(source_models)
+        .filter_map(|(bvid, mut source_model)| {
+            let video_model = videos.get(bvid)?;
+            source_model.video_id = Set(video_model.id);
+            video_list_model.set_relation_id(&mut source_model);
+            // 被过滤的视频同样会被记录，以便查看过滤的原因
+            source_model.filtered_reason = Set(content_filter.check_video(&video_model.clone().into_active_model()));
+            Some(source_model)
         })
         .collect::<Vec<_>>();
-    video::Entity::insert_many(video_models)
-        // 这里想表达的是 on 索引名，但 sea-orm 的 api 似乎只支持列名而不支持索引名，好在留空可以达到相同的目的
-        .on_conflict(OnConflict::new().do_nothing().to_owned())
+    video_source::Entity::insert_many(source_models)
+        .on_conflict(
+            OnConflict::columns([
+                video_source::Column::SourceType,
+                video_source::Column::SourceId,
+                video_source::Column::VideoId,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
         .do_nothing()
         .exec(connection)
         .await?;
@@ -73,17 +293,45 @@ pub async fn create_videos(
 /// 尝试创建 Page Model，如果发生冲突则忽略
 pub async fn create_pages(
     pages_info: Vec<PageInfo>,
-    video_model: &bili_sync_entity::video::Model,
+    source_model: &video_source::Model,
     connection: &DatabaseTransaction,
 ) -> Result<()> {
     let page_models = pages_info
         .into_iter()
-        .map(|p| p.into_active_model(video_model))
+        .map(|p| p.into_active_model(source_model))
         .collect::<Vec<page::ActiveModel>>();
+    insert_pages(page_models, connection).await
+}
+
+/// 为视频来源复制同一视频在其它视频来源中已有的分页信息，不复制下载路径与状态
+pub async fn copy_pages(
+    pages: &[page::Model],
+    source_model: &video_source::Model,
+    connection: &DatabaseTransaction,
+) -> Result<()> {
+    let page_models = pages
+        .iter()
+        .map(|p| page::ActiveModel {
+            video_source_id: Set(source_model.id),
+            cid: Set(p.cid),
+            pid: Set(p.pid),
+            name: Set(p.name.clone()),
+            width: Set(p.width),
+            height: Set(p.height),
+            duration: Set(p.duration),
+            image: Set(p.image.clone()),
+            download_status: Set(0),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+    insert_pages(page_models, connection).await
+}
+
+async fn insert_pages(page_models: Vec<page::ActiveModel>, connection: &DatabaseTransaction) -> Result<()> {
     for page_chunk in page_models.chunks(50) {
         page::Entity::insert_many(page_chunk.to_vec())
             .on_conflict(
-                OnConflict::columns([page::Column::VideoId, page::Column::Pid])
+                OnConflict::columns([page::Column::VideoSourceId, page::Column::Pid])
                     .do_nothing()
                     .to_owned(),
             )
@@ -94,12 +342,15 @@ pub async fn create_pages(
     Ok(())
 }
 
-/// 更新视频 model 的下载状态
-pub async fn update_videos_model(videos: Vec<video::ActiveModel>, connection: &DatabaseConnection) -> Result<()> {
-    video::Entity::insert_many(videos)
+/// 更新视频在视频来源中的下载状态
+pub async fn update_videos_model(
+    videos: Vec<video_source::ActiveModel>,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    video_source::Entity::insert_many(videos)
         .on_conflict(
-            OnConflict::column(video::Column::Id)
-                .update_columns([video::Column::DownloadStatus, video::Column::Path])
+            OnConflict::column(video_source::Column::Id)
+                .update_columns([video_source::Column::DownloadStatus, video_source::Column::Path])
                 .to_owned(),
         )
         .exec(connection)
@@ -107,11 +358,17 @@ pub async fn update_videos_model(videos: Vec<video::ActiveModel>, connection: &D
     Ok(())
 }
 
-/// 更新视频页 model 的下载状态
+/// 更新视频页 model 的下载状态、路径与下载的视频流
 pub async fn update_pages_model(pages: Vec<page::ActiveModel>, connection: &DatabaseConnection) -> Result<()> {
     let query = page::Entity::insert_many(pages).on_conflict(
         OnConflict::column(page::Column::Id)
-            .update_columns([page::Column::DownloadStatus, page::Column::Path])
+            .update_columns([
+                page::Column::DownloadStatus,
+                page::Column::Path,
+                page::Column::VideoQuality,
+                page::Column::VideoCodec,
+                page::Column::AudioQuality,
+            ])
             .to_owned(),
     );
     query.exec(connection).await?;
@@ -16,7 +16,7 @@ pub enum NFOMode {
 }
 
 pub enum ModelWrapper<'a> {
-    Video(&'a video::Model),
+    Video(&'a video::Model, &'a video_source::Model),
     Page(&'a page::Model),
 }
 
@@ -33,9 +33,9 @@ impl NFOSerializer<'_> {
         let mut tokio_buffer = tokio::io::BufWriter::new(&mut buffer);
         let mut writer = Writer::new_with_indent(&mut tokio_buffer, b' ', 4);
         match self {
-            NFOSerializer(ModelWrapper::Video(v), NFOMode::MOVIE) => {
+            NFOSerializer(ModelWrapper::Video(v, s), NFOMode::MOVIE) => {
                 let nfo_time = match nfo_time_type {
-                    NFOTimeType::FavTime => v.favtime,
+                    NFOTimeType::FavTime => s.favtime,
                     NFOTimeType::PubTime => v.pubtime,
                 };
                 writer
@@ -90,9 +90,9 @@ impl NFOSerializer<'_> {
                     })
                     .await?;
             }
-            NFOSerializer(ModelWrapper::Video(v), NFOMode::TVSHOW) => {
+            NFOSerializer(ModelWrapper::Video(v, s), NFOMode::TVSHOW) => {
                 let nfo_time = match nfo_time_type {
-                    NFOTimeType::FavTime => v.favtime,
+                    NFOTimeType::FavTime => s.favtime,
                     NFOTimeType::PubTime => v.pubtime,
                 };
                 writer
@@ -147,7 +147,7 @@ impl NFOSerializer<'_> {
                     })
                     .await?;
             }
-            NFOSerializer(ModelWrapper::Video(v), NFOMode::UPPER) => {
+            NFOSerializer(ModelWrapper::Video(v, _), NFOMode::UPPER) => {
                 writer
                     .create_element("person")
                     .write_inner_content_async::<_, _, Error>(|writer| async move {
@@ -223,10 +223,6 @@ mod tests {
             name: "name".to_string(),
             upper_id: 1,
             upper_name: "upper_name".to_string(),
-            favtime: chrono::NaiveDateTime::new(
-                chrono::NaiveDate::from_ymd_opt(2022, 2, 2).unwrap(),
-                chrono::NaiveTime::from_hms_opt(2, 2, 2).unwrap(),
-            ),
             pubtime: chrono::NaiveDateTime::new(
                 chrono::NaiveDate::from_ymd_opt(2033, 3, 3).unwrap(),
                 chrono::NaiveTime::from_hms_opt(3, 3, 3).unwrap(),
@@ -235,8 +231,15 @@ mod tests {
             tags: Some(serde_json::json!(["tag1", "tag2"])),
             ..Default::default()
         };
+        let video_source = video_source::Model {
+            favtime: chrono::NaiveDateTime::new(
+                chrono::NaiveDate::from_ymd_opt(2022, 2, 2).unwrap(),
+                chrono::NaiveTime::from_hms_opt(2, 2, 2).unwrap(),
+            ),
+            ..Default::default()
+        };
         assert_eq!(
-            NFOSerializer(ModelWrapper::Video(&video), NFOMode::MOVIE)
+            NFOSerializer(ModelWrapper::Video(&video, &video_source), NFOMode::MOVIE)
                 .generate_nfo(&NFOTimeType::PubTime)
                 .await
                 .unwrap(),
@@ -257,7 +260,7 @@ mod tests {
 </movie>"#,
         );
         assert_eq!(
-            NFOSerializer(ModelWrapper::Video(&video), NFOMode::TVSHOW)
+            NFOSerializer(ModelWrapper::Video(&video, &video_source), NFOMode::TVSHOW)
                 .generate_nfo(&NFOTimeType::FavTime)
                 .await
                 .unwrap(),
@@ -278,7 +281,7 @@ mod tests {
 </tvshow>"#,
         );
         assert_eq!(
-            NFOSerializer(ModelWrapper::Video(&video), NFOMode::UPPER)
+            NFOSerializer(ModelWrapper::Video(&video, &video_source), NFOMode::UPPER)
                 .generate_nfo(&NFOTimeType::FavTime)
                 .await
                 .unwrap(),
@@ -0,0 +1,207 @@
+use core::str;
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::Deserialize;
+
+use crate::bilibili::Dimension;
+
+/// ffprobe 输出的媒体文件信息，仅包含校验需要的字段
+#[derive(Deserialize)]
+struct ProbeOutput {
+    format: ProbeFormat,
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    format_name: String,
+    // ffprobe 输出的数值均为字符串
+    duration: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_type: String,
+    width: Option<u32>,
+    height: Option<u32>,
+    #[serde(default)]
+    disposition: ProbeDisposition,
+}
+
+#[derive(Deserialize, Default)]
+struct ProbeDisposition {
+    #[serde(default)]
+    attached_pic: u8,
+}
+
+/// 下载完成的视频文件应当满足的条件
+pub struct Expectation<'a> {
+    /// 容器格式，需要出现在 ffprobe 输出的 format_name 中
+    pub container: &'a str,
+    /// 视频时长，单位为秒，为 None 时不校验
+    pub duration: Option<u32>,
+    /// 是否包含视频流，作为封面嵌入的图片不计入
+    pub video: bool,
+    /// 视频分辨率，仅校验宽高比，因为下载的视频流的分辨率可能低于原始分辨率
+    pub dimension: Option<&'a Dimension>,
+    /// 是否包含音频流，为 None 时不校验
+    pub audio: Option<bool>,
+}
+
+/// 使用 ffprobe 校验视频文件的完整性，duration_tolerance 为允许的时长误差，单位为秒
+pub async fn verify_video(path: &Path, expectation: &Expectation<'_>, duration_tolerance: u32) -> Result<()> {
+    let output = tokio::process::Command::new("ffprobe")
+        .args(["-v", "error", "-print_format", "json", "-show_format", "-show_streams"])
+        .arg(path)
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!("ffprobe error: {}", str::from_utf8(&output.stderr).unwrap_or("unknown"));
+    }
+    let probe: ProbeOutput = serde_json::from_slice(&output.stdout).context("invalid ffprobe output")?;
+    check(&probe, expectation, duration_tolerance)
+}
+
+fn check(probe: &ProbeOutput, expectation: &Expectation<'_>, duration_tolerance: u32) -> Result<()> {
+    ensure!(
+        probe
+            .format
+            .format_name
+            .split(',')
+            .any(|name| name == expectation.container),
+        "container {} mismatch, expected {}",
+        probe.format.format_name,
+        expectation.container
+    );
+    if let Some(expected) = expectation.duration.filter(|duration| *duration > 0) {
+        let duration = probe
+            .format
+            .duration
+            .as_deref()
+            .and_then(|duration| duration.parse::<f64>().ok())
+            .context("duration not found")?;
+        ensure!(
+            (duration - expected as f64).abs() <= duration_tolerance as f64,
+            "duration {:.1}s mismatch, expected {}s",
+            duration,
+            expected
+        );
+    }
+    let videos = probe
+        .streams
+        .iter()
+        .filter(|stream| stream.codec_type == "video" && stream.disposition.attached_pic == 0)
+        .collect::<Vec<_>>();
+    let audios = probe
+        .streams
+        .iter()
+        .filter(|stream| stream.codec_type == "audio")
+        .count();
+    ensure!(
+        videos.len() == expectation.video as usize,
+        "found {} video streams, expected {}",
+        videos.len(),
+        expectation.video as usize
+    );
+    if let Some(audio) = expectation.audio {
+        ensure!(
+            audios == audio as usize,
+            "found {} audio streams, expected {}",
+            audios,
+            audio as usize
+        );
+    }
+    let video = videos.first();
+    if let (Some(expected), Some(width), Some(height)) = (
+        expectation.dimension,
+        video.and_then(|video| video.width),
+        video.and_then(|video| video.height),
+    ) {
+        if expected.width > 0 && expected.height > 0 && width > 0 && height > 0 {
+            let (ratio, expected_ratio) = (
+                width as f64 / height as f64,
+                expected.width as f64 / expected.height as f64,
+            );
+            // 转码时分辨率会被调整为偶数，允许少量的误差
+            ensure!(
+                (ratio / expected_ratio - 1.0).abs() <= 0.05,
+                "resolution {}x{} mismatch, expected aspect ratio of {}x{}",
+                width,
+                height,
+                expected.width,
+                expected.height
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_probe() {
+        let probe: ProbeOutput = serde_json::from_str(
+            r#"{
+                "streams": [
+                    { "index": 0, "codec_type": "video", "width": 1920, "height": 1080 },
+                    { "index": 1, "codec_type": "audio" }
+                ],
+                "format": { "format_name": "mov,mp4,m4a,3gp,3g2,mj2", "duration": "120.043000" }
+            }"#,
+        )
+        .unwrap();
+        let dimension = Dimension {
+            width: 3840,
+            height: 2160,
+            rotate: 0,
+        };
+        let mut expectation = Expectation {
+            container: "mp4",
+            duration: Some(120),
+            video: true,
+            dimension: Some(&dimension),
+            audio: Some(true),
+        };
+        assert!(check(&probe, &expectation, 2).is_ok());
+        expectation.audio = Some(false);
+        assert!(check(&probe, &expectation, 2).is_err());
+        expectation.audio = Some(true);
+        // 截断的文件时长不足
+        expectation.duration = Some(300);
+        assert!(check(&probe, &expectation, 2).is_err());
+        expectation.duration = Some(120);
+        let portrait = Dimension {
+            width: 1080,
+            height: 1920,
+            rotate: 0,
+        };
+        expectation.dimension = Some(&portrait);
+        assert!(check(&probe, &expectation, 2).is_err());
+        expectation.dimension = None;
+        expectation.container = "flv";
+        assert!(check(&probe, &expectation, 2).is_err());
+        // 仅包含音频与封面的文件
+        let probe: ProbeOutput = serde_json::from_str(
+            r#"{
+                "streams": [
+                    { "index": 0, "codec_type": "audio" },
+                    { "index": 1, "codec_type": "video", "width": 480, "height": 270, "disposition": { "attached_pic": 1 } }
+                ],
+                "format": { "format_name": "flac", "duration": "120.000000" }
+            }"#,
+        )
+        .unwrap();
+        let expectation = Expectation {
+            container: "flac",
+            duration: Some(120),
+            video: false,
+            dimension: None,
+            audio: Some(true),
+        };
+        assert!(check(&probe, &expectation, 2).is_ok());
+    }
+}
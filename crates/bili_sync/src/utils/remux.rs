@@ -0,0 +1,547 @@
+//! 将 DASH 的视频流与音频流合并为单个 MP4 文件的简易实现，不依赖外部的 FFmpeg
+//!
+//! b 站的 DASH 流均为 fragmented MP4，每个文件仅包含一个轨道，结构为 ftyp、moov 与若干 moof + mdat 的分片。
+//! 合并时以视频的 moov 为基础加入音频的 trak 与 trex，再将两者的分片按照解码时间交错写入，
+//! 分片中的数据偏移均相对于 moof 的起始位置，因此 mdat 的内容可以原样复制而无需解析具体的采样。
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+
+/// tfhd 中表示存在 base_data_offset 的标记，此时数据偏移为文件中的绝对位置，无法直接移动分片
+const TFHD_BASE_DATA_OFFSET: u32 = 0x01;
+const TFHD_SAMPLE_DESCRIPTION_INDEX: u32 = 0x02;
+const TFHD_DEFAULT_SAMPLE_DURATION: u32 = 0x08;
+const TRUN_DATA_OFFSET: u32 = 0x01;
+const TRUN_FIRST_SAMPLE_FLAGS: u32 = 0x04;
+const TRUN_SAMPLE_DURATION: u32 = 0x100;
+const TRUN_SAMPLE_SIZE: u32 = 0x200;
+const TRUN_SAMPLE_FLAGS: u32 = 0x400;
+const TRUN_SAMPLE_CTO: u32 = 0x800;
+
+/// 单个分片，moof 保存在内存中，mdat 仅记录在源文件中的位置
+struct Fragment {
+    moof: Vec<u8>,
+    mdats: Vec<Range<u64>>,
+    decode_time: u64,
+    duration: u64,
+}
+
+/// 仅包含单个轨道的 fragmented MP4 文件
+struct Track {
+    file: File,
+    moov: Vec<u8>,
+    fragments: Vec<Fragment>,
+    timescale: u32,
+}
+
+/// 合并视频流与音频流，失败时删除未写完的输出文件
+pub fn remux(video_path: &Path, audio_path: &Path, output_path: &Path) -> Result<()> {
+    let res = remux_inner(video_path, audio_path, output_path);
+    if res.is_err() {
+        let _ = std::fs::remove_file(output_path);
+    }
+    res
+}
+
+fn remux_inner(video_path: &Path, audio_path: &Path, output_path: &Path) -> Result<()> {
+    let mut video = Track::open(video_path).context("parse video stream failed")?;
+    let mut audio = Track::open(audio_path).context("parse audio stream failed")?;
+    let video_id = track_id(&video.moov)?;
+    let audio_id = video_id.checked_add(1).context("invalid track id")?;
+    audio.set_track_id(audio_id)?;
+    let moov = merge_moov(&video, &audio, audio_id)?;
+    let mut output = BufWriter::new(File::create(output_path)?);
+    output.write_all(&ftyp())?;
+    output.write_all(&moov)?;
+    // 按照解码时间交错写入两个轨道的分片，时间相同时视频在前
+    let (mut video_idx, mut audio_idx, mut sequence) = (0, 0, 1);
+    while video_idx < video.fragments.len() || audio_idx < audio.fragments.len() {
+        let take_video = match (video.fragments.get(video_idx), audio.fragments.get(audio_idx)) {
+            (Some(v), Some(a)) => {
+                compare_time(v.decode_time, video.timescale, a.decode_time, audio.timescale) != Ordering::Greater
+            }
+            (Some(_), None) => true,
+            _ => false,
+        };
+        let (track, idx) = if take_video {
+            (&mut video, &mut video_idx)
+        } else {
+            (&mut audio, &mut audio_idx)
+        };
+        let fragment = &mut track.fragments[*idx];
+        set_sequence_number(&mut fragment.moof, sequence)?;
+        output.write_all(&fragment.moof)?;
+        for mdat in &fragment.mdats {
+            track.file.seek(SeekFrom::Start(mdat.start))?;
+            let copied = std::io::copy(&mut (&mut track.file).take(mdat.end - mdat.start), &mut output)?;
+            ensure!(copied == mdat.end - mdat.start, "unexpected end of mdat");
+        }
+        *idx += 1;
+        sequence += 1;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+impl Track {
+    fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let (mut moov, mut fragments) = (None, Vec::<Fragment>::new());
+        let mut offset = 0;
+        while offset < len {
+            file.seek(SeekFrom::Start(offset))?;
+            let mut header = [0u8; 16];
+            file.read_exact(&mut header[..8])?;
+            let kind = [header[4], header[5], header[6], header[7]];
+            let (size, header_len) = match u32::from_be_bytes(header[..4].try_into()?) {
+                0 => (len - offset, 8),
+                1 => {
+                    file.read_exact(&mut header[8..])?;
+                    (u64::from_be_bytes(header[8..].try_into()?), 16)
+                }
+                size => (size as u64, 8),
+            };
+            ensure!(
+                size >= header_len && offset + size <= len,
+                "invalid box size at {}",
+                offset
+            );
+            match &kind {
+                b"moov" | b"moof" => {
+                    let mut data = vec![0u8; usize::try_from(size)?];
+                    file.seek(SeekFrom::Start(offset))?;
+                    file.read_exact(&mut data)?;
+                    if &kind == b"moov" {
+                        moov = Some(data);
+                    } else {
+                        fragments.push(Fragment::parse(data)?);
+                    }
+                }
+                b"mdat" => fragments
+                    .last_mut()
+                    .context("mdat before moof, not a fragmented mp4")?
+                    .mdats
+                    .push(offset..offset + size),
+                // sidx、styp、free 等盒子在合并后不再需要
+                _ => {}
+            }
+            offset += size;
+        }
+        let moov = moov.context("moov not found")?;
+        let trak = find_path(&moov, &[b"trak"]).context("trak not found")?;
+        ensure!(
+            children(&moov)?.iter().filter(|(kind, _)| kind == b"trak").count() == 1,
+            "only single track is supported"
+        );
+        let mdhd = find_path(&moov[trak.clone()], &[b"mdia", b"mdhd"]).context("mdhd not found")?;
+        let mdhd = &moov[trak][mdhd];
+        let timescale = match mdhd[8] {
+            0 => read_u32(mdhd, 20)?,
+            _ => read_u32(mdhd, 28)?,
+        };
+        ensure!(timescale > 0, "invalid timescale");
+        // trun 与 tfhd 均未指定采样时长时使用 trex 中的默认值
+        let default_duration = match find_path(&moov, &[b"mvex", b"trex"]) {
+            Some(trex) => read_u32(&moov[trex], 20)?,
+            None => 0,
+        };
+        for fragment in fragments.iter_mut() {
+            fragment.duration = fragment.sample_durations(default_duration)?;
+        }
+        Ok(Self {
+            file,
+            moov,
+            fragments,
+            timescale,
+        })
+    }
+
+    /// 修改轨道的 id，需要同时修改 tkhd、trex 与每个分片的 tfhd
+    fn set_track_id(&mut self, id: u32) -> Result<()> {
+        let tkhd = find_path(&self.moov, &[b"trak", b"tkhd"]).context("tkhd not found")?;
+        let offset = if self.moov[tkhd.start + 8] == 0 { 20 } else { 28 };
+        write_u32(&mut self.moov, tkhd.start + offset, id)?;
+        if let Some(trex) = find_path(&self.moov, &[b"mvex", b"trex"]) {
+            write_u32(&mut self.moov, trex.start + 12, id)?;
+        }
+        for fragment in self.fragments.iter_mut() {
+            let tfhd = find_path(&fragment.moof, &[b"traf", b"tfhd"]).context("tfhd not found")?;
+            write_u32(&mut fragment.moof, tfhd.start + 12, id)?;
+        }
+        Ok(())
+    }
+
+    /// 轨道的结束时间，单位为轨道自身的 timescale
+    fn end_time(&self) -> u64 {
+        self.fragments
+            .iter()
+            .map(|fragment| fragment.decode_time + fragment.duration)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+impl Fragment {
+    fn parse(moof: Vec<u8>) -> Result<Self> {
+        let trafs = children(&moof)?.into_iter().filter(|(kind, _)| kind == b"traf").count();
+        ensure!(trafs == 1, "found {} traf in moof, expected 1", trafs);
+        let tfhd = find_path(&moof, &[b"traf", b"tfhd"]).context("tfhd not found")?;
+        ensure!(
+            read_u32(&moof[tfhd], 8)? & 0x00FF_FFFF & TFHD_BASE_DATA_OFFSET == 0,
+            "absolute base data offset is not supported"
+        );
+        let decode_time = match find_path(&moof, &[b"traf", b"tfdt"]) {
+            Some(tfdt) if moof[tfdt.start + 8] == 1 => read_u64(&moof[tfdt], 12)?,
+            Some(tfdt) => read_u32(&moof[tfdt], 12)? as u64,
+            None => bail!("tfdt not found"),
+        };
+        Ok(Self {
+            moof,
+            mdats: Vec::new(),
+            decode_time,
+            duration: 0,
+        })
+    }
+
+    /// 计算分片中所有采样的总时长
+    fn sample_durations(&self, trex_duration: u32) -> Result<u64> {
+        let traf = find_path(&self.moof, &[b"traf"]).context("traf not found")?;
+        let traf = &self.moof[traf];
+        let tfhd = find_path(traf, &[b"tfhd"]).context("tfhd not found")?;
+        let tfhd = &traf[tfhd];
+        let flags = read_u32(tfhd, 8)? & 0x00FF_FFFF;
+        let mut default_duration = trex_duration;
+        if flags & TFHD_DEFAULT_SAMPLE_DURATION != 0 {
+            let mut offset = 16;
+            if flags & TFHD_SAMPLE_DESCRIPTION_INDEX != 0 {
+                offset += 4;
+            }
+            default_duration = read_u32(tfhd, offset)?;
+        }
+        let mut total = 0;
+        for (kind, range) in children(traf)? {
+            if &kind != b"trun" {
+                continue;
+            }
+            let trun = &traf[range];
+            let flags = read_u32(trun, 8)? & 0x00FF_FFFF;
+            let count = read_u32(trun, 12)? as u64;
+            if flags & TRUN_SAMPLE_DURATION == 0 {
+                total += count * default_duration as u64;
+                continue;
+            }
+            let mut offset = 16;
+            if flags & TRUN_DATA_OFFSET != 0 {
+                offset += 4;
+            }
+            if flags & TRUN_FIRST_SAMPLE_FLAGS != 0 {
+                offset += 4;
+            }
+            let sample_len = [
+                TRUN_SAMPLE_DURATION,
+                TRUN_SAMPLE_SIZE,
+                TRUN_SAMPLE_FLAGS,
+                TRUN_SAMPLE_CTO,
+            ]
+            .into_iter()
+            .filter(|flag| flags & flag != 0)
+            .count()
+                * 4;
+            for idx in 0..count as usize {
+                total += read_u32(trun, offset + idx * sample_len)? as u64;
+            }
+        }
+        Ok(total)
+    }
+}
+
+/// 以视频的 moov 为基础，加入音频的 trak 与 trex，并更新总时长与下一个轨道的 id
+fn merge_moov(video: &Track, audio: &Track, audio_id: u32) -> Result<Vec<u8>> {
+    let mvhd = find_path(&video.moov, &[b"mvhd"]).context("mvhd not found")?;
+    let mvhd_data = &video.moov[mvhd.clone()];
+    let movie_timescale = match mvhd_data[8] {
+        0 => read_u32(mvhd_data, 20)?,
+        _ => read_u32(mvhd_data, 28)?,
+    };
+    ensure!(movie_timescale > 0, "invalid movie timescale");
+    let duration = [(video.end_time(), video.timescale), (audio.end_time(), audio.timescale)]
+        .into_iter()
+        .map(|(time, timescale)| (time as u128 * movie_timescale as u128 / timescale as u128) as u64)
+        .max()
+        .unwrap_or_default();
+    let audio_trak = find_path(&audio.moov, &[b"trak"]).context("audio trak not found")?;
+    let audio_trex = find_path(&audio.moov, &[b"mvex", b"trex"]).context("audio trex not found")?;
+    let mut body = Vec::new();
+    let video_children = children(&video.moov)?;
+    let last_trak = video_children
+        .iter()
+        .rposition(|(kind, _)| kind == b"trak")
+        .context("video trak not found")?;
+    for (idx, (kind, range)) in video_children.iter().enumerate() {
+        match kind {
+            b"mvhd" => {
+                let mut mvhd = video.moov[range.clone()].to_vec();
+                if mvhd[8] == 0 {
+                    write_u32(&mut mvhd, 24, u32::try_from(duration).unwrap_or(u32::MAX))?;
+                } else {
+                    write_u64(&mut mvhd, 32, duration)?;
+                }
+                // next_track_ID 位于 mvhd 的末尾
+                let len = mvhd.len();
+                write_u32(&mut mvhd, len - 4, audio_id + 1)?;
+                body.extend_from_slice(&mvhd);
+            }
+            b"mvex" => {
+                let mut mvex = Vec::new();
+                // mehd 记录所有分片的总时长，便于播放器在读取全部分片前得知视频的长度
+                mvex.extend_from_slice(&full_box(b"mehd", 1, &duration.to_be_bytes()));
+                for (kind, child) in children(&video.moov[range.clone()])? {
+                    if &kind != b"mehd" {
+                        mvex.extend_from_slice(&video.moov[range.start + child.start..range.start + child.end]);
+                    }
+                }
+                mvex.extend_from_slice(&audio.moov[audio_trex.clone()]);
+                body.extend_from_slice(&make_box(b"mvex", &mvex));
+            }
+            _ => body.extend_from_slice(&video.moov[range.clone()]),
+        }
+        if idx == last_trak {
+            body.extend_from_slice(&audio.moov[audio_trak.clone()]);
+        }
+    }
+    Ok(make_box(b"moov", &body))
+}
+
+fn ftyp() -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(b"isom");
+    body.extend_from_slice(&0x200u32.to_be_bytes());
+    for brand in [b"isom", b"iso6", b"mp41"] {
+        body.extend_from_slice(brand);
+    }
+    make_box(b"ftyp", &body)
+}
+
+fn track_id(moov: &[u8]) -> Result<u32> {
+    let tkhd = find_path(moov, &[b"trak", b"tkhd"]).context("tkhd not found")?;
+    let tkhd = &moov[tkhd];
+    read_u32(tkhd, if tkhd[8] == 0 { 20 } else { 28 })
+}
+
+fn set_sequence_number(moof: &mut [u8], sequence: u32) -> Result<()> {
+    let mfhd = find_path(moof, &[b"mfhd"]).context("mfhd not found")?;
+    write_u32(moof, mfhd.start + 12, sequence)
+}
+
+fn compare_time(a: u64, a_timescale: u32, b: u64, b_timescale: u32) -> Ordering {
+    (a as u128 * b_timescale as u128).cmp(&(b as u128 * a_timescale as u128))
+}
+
+/// 解析容器盒子的所有子盒子，返回子盒子的类型与其在 data 中的完整范围
+fn children(data: &[u8]) -> Result<Vec<([u8; 4], Range<usize>)>> {
+    let header_len = if read_u32(data, 0)? == 1 { 16 } else { 8 };
+    let mut result = Vec::new();
+    let mut offset = header_len;
+    while offset < data.len() {
+        let kind: [u8; 4] = data.get(offset + 4..offset + 8).context("truncated box")?.try_into()?;
+        let size = match read_u32(data, offset)? {
+            0 => data.len() - offset,
+            1 => usize::try_from(read_u64(data, offset + 8)?)?,
+            size => size as usize,
+        };
+        ensure!(
+            size >= 8 && offset + size <= data.len(),
+            "invalid box size at {}",
+            offset
+        );
+        result.push((kind, offset..offset + size));
+        offset += size;
+    }
+    Ok(result)
+}
+
+/// 按照路径逐层查找第一个匹配的子盒子
+fn find_path(data: &[u8], path: &[&[u8; 4]]) -> Option<Range<usize>> {
+    let mut range = 0..data.len();
+    for kind in path {
+        let (_, child) = children(&data[range.clone()])
+            .ok()?
+            .into_iter()
+            .find(|(child_kind, _)| child_kind == *kind)?;
+        range = range.start + child.start..range.start + child.end;
+    }
+    Some(range)
+}
+
+fn make_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(body.len() + 8);
+    data.extend_from_slice(&(body.len() as u32 + 8).to_be_bytes());
+    data.extend_from_slice(kind);
+    data.extend_from_slice(body);
+    data
+}
+
+fn full_box(kind: &[u8; 4], version: u8, body: &[u8]) -> Vec<u8> {
+    let mut data = vec![version, 0, 0, 0];
+    data.extend_from_slice(body);
+    make_box(kind, &data)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    Ok(u32::from_be_bytes(
+        data.get(offset..offset + 4).context("truncated box")?.try_into()?,
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    Ok(u64::from_be_bytes(
+        data.get(offset..offset + 8).context("truncated box")?.try_into()?,
+    ))
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) -> Result<()> {
+    data.get_mut(offset..offset + 4)
+        .context("truncated box")?
+        .copy_from_slice(&value.to_be_bytes());
+    Ok(())
+}
+
+fn write_u64(data: &mut [u8], offset: usize, value: u64) -> Result<()> {
+    data.get_mut(offset..offset + 8)
+        .context("truncated box")?
+        .copy_from_slice(&value.to_be_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 构造仅包含一个轨道的 fragmented MP4，每个分片包含 samples 个时长为 sample_duration 的采样
+    fn fragmented_mp4(track_id: u32, timescale: u32, sample_duration: u32, fragments: &[&[u8]]) -> Vec<u8> {
+        let mut mvhd = vec![0u8; 96];
+        mvhd[8..12].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[92..96].copy_from_slice(&(track_id + 1).to_be_bytes());
+        let mut tkhd = vec![0u8; 80];
+        tkhd[8..12].copy_from_slice(&track_id.to_be_bytes());
+        let mut mdhd = vec![0u8; 20];
+        mdhd[8..12].copy_from_slice(&timescale.to_be_bytes());
+        let trak = make_box(
+            b"trak",
+            &[
+                full_box(b"tkhd", 0, &tkhd),
+                make_box(b"mdia", &full_box(b"mdhd", 0, &mdhd)),
+            ]
+            .concat(),
+        );
+        let mut trex = vec![0u8; 20];
+        trex[..4].copy_from_slice(&track_id.to_be_bytes());
+        trex[8..12].copy_from_slice(&sample_duration.to_be_bytes());
+        let moov = make_box(
+            b"moov",
+            &[
+                full_box(b"mvhd", 0, &mvhd),
+                trak,
+                make_box(b"mvex", &full_box(b"trex", 0, &trex)),
+            ]
+            .concat(),
+        );
+        let mut data = [make_box(b"ftyp", b"iso5\0\0\0\x01iso5dash"), moov].concat();
+        let mut decode_time = 0u64;
+        for (idx, payload) in fragments.iter().enumerate() {
+            let samples = payload.len() as u32;
+            let mfhd = full_box(b"mfhd", 0, &(idx as u32 + 1).to_be_bytes());
+            // default-base-is-moof
+            let tfhd = make_box(
+                b"tfhd",
+                &[&0x0002_0000u32.to_be_bytes()[..], &track_id.to_be_bytes()].concat(),
+            );
+            let tfdt = full_box(b"tfdt", 1, &decode_time.to_be_bytes());
+            // 每个采样占一个字节，仅包含采样大小
+            let mut trun_body = Vec::new();
+            trun_body.extend_from_slice(&samples.to_be_bytes());
+            trun_body.extend_from_slice(&0u32.to_be_bytes());
+            for _ in 0..samples {
+                trun_body.extend_from_slice(&1u32.to_be_bytes());
+            }
+            let trun = make_box(
+                b"trun",
+                &[&(TRUN_DATA_OFFSET | TRUN_SAMPLE_SIZE).to_be_bytes()[..], &trun_body].concat(),
+            );
+            let traf = make_box(b"traf", &[tfhd, tfdt, trun].concat());
+            let mut moof = make_box(b"moof", &[mfhd, traf].concat());
+            // data_offset 指向 mdat 的内容
+            let data_offset = moof.len() as u32 + 8;
+            let trun = find_path(&moof, &[b"traf", b"trun"]).unwrap();
+            write_u32(&mut moof, trun.start + 16, data_offset).unwrap();
+            data.extend_from_slice(&make_box(b"sidx", &[0u8; 24]));
+            data.extend_from_slice(&moof);
+            data.extend_from_slice(&make_box(b"mdat", payload));
+            decode_time += (samples * sample_duration) as u64;
+        }
+        data
+    }
+
+    #[test]
+    fn test_remux() {
+        let dir = std::env::temp_dir().join(format!("bili-sync-remux-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (video_path, audio_path, output_path) = (dir.join("video"), dir.join("audio"), dir.join("output.mp4"));
+        // 视频每秒 2 个采样，音频每秒 4 个采样
+        std::fs::write(&video_path, fragmented_mp4(1, 1000, 500, &[b"vv", b"VV"])).unwrap();
+        std::fs::write(&audio_path, fragmented_mp4(1, 48000, 12000, &[b"aaaa", b"AAAA"])).unwrap();
+        remux(&video_path, &audio_path, &output_path).unwrap();
+        let output = std::fs::read(&output_path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        // 以一个虚拟的根盒子包裹输出，便于复用 children 解析
+        let root = make_box(b"root", &output);
+        let boxes = children(&root).unwrap();
+        let kinds = boxes
+            .iter()
+            .map(|(kind, _)| String::from_utf8_lossy(kind).to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            ["ftyp", "moov", "moof", "mdat", "moof", "mdat", "moof", "mdat", "moof", "mdat"]
+        );
+        let moov = &root[boxes[1].1.clone()];
+        let traks = children(moov)
+            .unwrap()
+            .into_iter()
+            .filter(|(kind, _)| kind == b"trak")
+            .count();
+        assert_eq!(traks, 2);
+        let mvhd = find_path(moov, &[b"mvhd"]).unwrap();
+        // 总时长为 2 秒，movie timescale 为 1000
+        assert_eq!(read_u32(&moov[mvhd.clone()], 24).unwrap(), 2000);
+        assert_eq!(read_u32(&moov[mvhd.clone()], mvhd.len() - 4).unwrap(), 3);
+        let mehd = find_path(moov, &[b"mvex", b"mehd"]).unwrap();
+        assert_eq!(read_u64(&moov[mehd], 12).unwrap(), 2000);
+        // 分片按照解码时间交错，音频分片的轨道 id 被修改为 2，序号重新编号
+        let fragments = boxes
+            .iter()
+            .filter(|(kind, _)| kind == b"moof")
+            .map(|(_, range)| {
+                let moof = &root[range.clone()];
+                let tfhd = find_path(moof, &[b"traf", b"tfhd"]).unwrap();
+                let mfhd = find_path(moof, &[b"mfhd"]).unwrap();
+                (
+                    read_u32(moof, tfhd.start + 12).unwrap(),
+                    read_u32(moof, mfhd.start + 12).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(fragments, [(1, 1), (2, 2), (1, 3), (2, 4)]);
+        let payloads = boxes
+            .iter()
+            .filter(|(kind, _)| kind == b"mdat")
+            .map(|(_, range)| &root[range.start + 8..range.end])
+            .collect::<Vec<_>>();
+        assert_eq!(payloads, [&b"vv"[..], b"aaaa", b"VV", b"AAAA"]);
+    }
+}
@@ -41,6 +41,17 @@ impl<const N: usize> Status<N> {
         }
     }
 
+    /// 检查某个子任务是否已经执行成功
+    pub fn is_ok(&self, offset: usize) -> bool {
+        self.get_status(offset) == STATUS_OK
+    }
+
+    /// 重置某个子任务的状态并清除完成标记，使其在下一轮重新执行
+    pub fn reset(&mut self, offset: usize) {
+        self.set_status(offset, 0);
+        self.set_completed(false);
+    }
+
     /// 设置最高位的完成标记
     fn set_completed(&mut self, completed: bool) {
         if completed {
@@ -147,11 +158,22 @@ mod test {
         assert_eq!(status.should_run(), [false, false, false]);
     }
 
+    #[test]
+    fn test_status_reset() {
+        let mut status = Status::<3>::from([7, 7, 7]);
+        assert!(status.get_completed());
+        status.reset(1);
+        assert!(!status.get_completed());
+        assert!(status.is_ok(0) && !status.is_ok(1));
+        assert_eq!(<[u32; 3]>::from(status), [7, 0, 7]);
+        assert_eq!(status.should_run(), [false, true, false]);
+    }
+
     #[test]
     fn test_status_convert() {
         let testcases = [[0, 0, 1], [1, 2, 3], [3, 1, 2], [3, 0, 7]];
         for testcase in testcases.iter() {
-            let status = Status::<3>::from(testcase.clone());
+            let status = Status::<3>::from(*testcase);
             assert_eq!(<[u32; 3]>::from(status), *testcase);
         }
     }
@@ -160,7 +182,7 @@ mod test {
     fn test_status_convert_and_update() {
         let testcases = [([0, 0, 1], [1, 7, 7]), ([3, 4, 3], [4, 4, 7]), ([3, 1, 7], [4, 7, 7])];
         for (before, after) in testcases.iter() {
-            let mut status = Status::<3>::from(before.clone());
+            let mut status = Status::<3>::from(*before);
             status.update_status(&[Err(anyhow!("")), Ok(()), Ok(())]);
             assert_eq!(<[u32; 3]>::from(status), *after);
         }
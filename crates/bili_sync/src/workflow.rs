@@ -1,62 +1,270 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use bili_sync_entity::*;
 use futures::stream::{FuturesOrdered, FuturesUnordered};
 use futures::{Future, Stream, StreamExt, TryStreamExt};
 use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::OnConflict;
 use sea_orm::ActiveValue::Set;
-use sea_orm::TransactionTrait;
+use sea_orm::{DatabaseTransaction, IntoActiveModel, TransactionTrait};
 use tokio::fs;
-use tokio::sync::Semaphore;
+use tokio::sync::{OnceCell, Semaphore};
 
 use crate::adapter::{video_list_from, Args, VideoListModel, VideoListModelEnum};
-use crate::bilibili::{BestStream, BiliClient, BiliError, Dimension, PageInfo, Video, VideoInfo};
-use crate::config::{PathSafeTemplate, ARGS, CONFIG, TEMPLATE};
-use crate::downloader::Downloader;
-use crate::error::{DownloadAbortError, ProcessPageError};
-use crate::utils::format_arg::{page_format_args, video_format_args};
+use crate::bilibili::{
+    self, parse_bvid, set_global_mixin_key, AudioQuality, Bangumi, BangumiItem, BestStream, BiliClient, BiliError,
+    DanmakuOption, Dimension, FilterOption, Following, PageInfo, PlayerInfo, Resource, Season, StreamQuality, User,
+    Video, VideoInfo,
+};
+use crate::config::{
+    ContentFilter, DedupeMode, NFOTimeType, OutputFormat, OutputOption, PathSafeTemplate, RemovalPolicy,
+    RetentionPolicy, SourceConfig, SourceOption, UnfollowPolicy, ARGS, CONFIG, TEMPLATE,
+};
+use crate::downloader::{remove_parts, Downloader};
+use crate::error::{DownloadAbortError, DownloadPausedError, ProcessPageError};
+use crate::utils::audio::AudioTags;
+use crate::utils::filenamify::filenamify;
+use crate::utils::format_arg::{favorite_format_args, page_format_args, video_format_args};
+use crate::utils::fs::{
+    link_file, move_path, page_file_suffix, path_size, remove_path, remove_video_dir_if_empty, unlink_file, video_files,
+};
+use crate::utils::mkv::MkvSources;
 use crate::utils::model::{
-    create_pages, create_videos, filter_unfilled_videos, filter_unhandled_video_pages, update_pages_model,
-    update_videos_model,
+    copy_pages, create_pages, create_videos, filter_adhoc_videos, filter_recheck_videos, filter_refresh_videos,
+    filter_source_video_pages, filter_unfilled_videos, filter_unhandled_video_pages, filter_upgrade_pages,
+    filter_video_pages, filter_video_sources, update_pages_model, update_videos_model,
 };
 use crate::utils::nfo::{ModelWrapper, NFOMode, NFOSerializer};
+use crate::utils::probe::{verify_video, Expectation};
 use crate::utils::status::{PageStatus, VideoStatus};
 
 /// 完整地处理某个视频列表
 pub async fn process_video_list(
     args: Args<'_>,
     bili_client: &BiliClient,
-    path: &Path,
+    source: &SourceConfig,
+    full_rescan: bool,
     connection: &DatabaseConnection,
 ) -> Result<()> {
     // 从参数中获取视频列表的 Model 与视频流
-    let (video_list_model, video_streams) = video_list_from(args, path, bili_client, connection).await?;
-    // 从视频流中获取新视频的简要信息，写入数据库
-    refresh_video_list(&video_list_model, video_streams, connection).await?;
-    // 单独请求视频详情接口，获取视频的详情信息与所有的分页，写入数据库
-    fetch_video_details(bili_client, &video_list_model, connection).await?;
+    let (video_list_model, video_streams) = video_list_from(args, &source.path, bili_client, connection).await?;
+    // 单独下载的视频不受时间安排的限制
+    let scheduled = !matches!(args, Args::Adhoc { .. });
+    if scheduled && !CONFIG.schedule.scan_allowed() {
+        info!("当前不在允许扫描的时间段内，跳过扫描..");
+    } else {
+        // 从视频流中获取新视频的简要信息，写入数据库
+        refresh_video_list(
+            &video_list_model,
+            video_streams,
+            source,
+            full_rescan || source.full_rescan(),
+            connection,
+        )
+        .await?;
+        // 过滤规则可能在两次扫描之间被修改，重新检查被过滤的视频
+        refilter_videos(&video_list_model, source.content_filter(), connection).await?;
+        // 按照保留策略删除过期的视频，避免在之后获取其详情与下载
+        apply_retention(&video_list_model, source, connection).await?;
+        // 单独请求视频详情接口，获取视频的详情信息与所有的分页，写入数据库
+        fetch_video_details(bili_client, &video_list_model, source, connection).await?;
+        if CONFIG.page_recheck.enabled {
+            // 检查近期发布的视频是否新增了分页
+            recheck_video_pages(bili_client, &video_list_model, connection).await?;
+        }
+        if CONFIG.metadata_refresh.enabled {
+            // 刷新已下载视频的元数据，必要时重命名本地文件
+            refresh_video_metadata(bili_client, &video_list_model, connection).await?;
+        }
+        if CONFIG.quality_upgrade.enabled {
+            // 检查近期发布的视频是否有更好的视频流
+            upgrade_video_quality(bili_client, &video_list_model, source, connection).await?;
+        }
+    }
     if ARGS.scan_only {
         warn!("已开启仅扫描模式，跳过视频下载..");
+    } else if scheduled && !CONFIG.schedule.download_allowed() {
+        info!("当前不在允许下载的时间段内，跳过视频下载..");
     } else {
         // 从数据库中查找所有未下载的视频与分页，下载并处理
-        download_unprocessed_videos(bili_client, &video_list_model, connection).await?;
+        download_unprocessed_videos(bili_client, &video_list_model, source, connection).await?;
     }
     Ok(())
 }
 
+/// 单独下载命令行中指定的视频，这些视频会被记录在 adhoc 下，已经下载完成的视频不会重复下载
+/// 返回指定视频的标题与实际的保存位置，之前已经记录的视频保存在首次下载时的位置，而非本次指定的位置
+pub async fn download_adhoc_videos(
+    inputs: &[String],
+    bili_client: &BiliClient,
+    path: &Path,
+    connection: &DatabaseConnection,
+) -> Result<Vec<(String, String)>> {
+    let bvids = inputs
+        .iter()
+        .map(|input| parse_bvid(input))
+        .collect::<Result<Vec<_>>>()?;
+    let mixin_key: Option<String> = bili_client.wbi_img().await?.into();
+    set_global_mixin_key(mixin_key.context("failed to parse mixin key")?);
+    // 单独下载的视频列表仅包含本次指定的视频，不能据此判断其它视频是否被移出，也不应用保留策略
+    let source = SourceConfig {
+        path: path.to_path_buf(),
+        options: SourceOption {
+            removal_policy: Some(RemovalPolicy::Ignore),
+            retention: Some(RetentionPolicy::default()),
+            ..Default::default()
+        },
+    };
+    process_video_list(Args::Adhoc { bvids: &bvids }, bili_client, &source, false, connection).await?;
+    Ok(filter_adhoc_videos(&bvids, connection)
+        .await?
+        .into_iter()
+        .map(|(source_model, video_model)| (video_model.name, source_model.path))
+        .collect())
+}
+
+/// 获取当前账号的关注列表，返回所有需要作为投稿处理的 UP 主 id 与保存路径
+/// 通过该方式添加的投稿会被标记，以便在取消关注后根据配置决定是否继续同步
+pub async fn refresh_following_list(
+    bili_client: &BiliClient,
+    connection: &DatabaseConnection,
+) -> Result<Vec<(String, SourceConfig)>> {
+    let following_config = &CONFIG.following;
+    let mid = CONFIG
+        .credential
+        .load()
+        .as_deref()
+        .context("no credential found")?
+        .dedeuserid
+        .clone();
+    info!("开始获取关注列表..");
+    // 获取过程中出错时直接返回，避免把不完整的关注列表当作取消关注处理
+    let uppers = Following::new(bili_client, mid)
+        .into_upper_stream()
+        .try_filter(|upper| {
+            futures::future::ready(following_config.tags.is_empty() || upper.in_tags(&following_config.tags))
+        })
+        .try_collect::<Vec<_>>()
+        .await?;
+    let mut submissions = uppers
+        .into_iter()
+        .map(|upper| {
+            (
+                upper.mid,
+                following_config.path.path.join(filenamify(&upper.name)),
+                upper.name,
+            )
+        })
+        .collect::<Vec<_>>();
+    if !submissions.is_empty() {
+        submission::Entity::insert_many(submissions.iter().map(|(mid, path, name)| submission::ActiveModel {
+            upper_id: Set(*mid),
+            upper_name: Set(name.clone()),
+            path: Set(path.to_string_lossy().to_string()),
+            from_following: Set(true),
+            ..Default::default()
+        }))
+        // 已经存在的投稿可能是手动配置的，不修改其标记，仅标记由关注列表新增的投稿
+        .on_conflict(OnConflict::column(submission::Column::UpperId).do_nothing().to_owned())
+        .do_nothing()
+        .exec(connection)
+        .await?;
+    }
+    info!("获取关注列表完成，共 {} 个 UP 主", submissions.len());
+    if following_config.unfollow_policy == UnfollowPolicy::Keep {
+        // 已经取消关注，但之前通过关注列表添加的 UP 主，继续使用原有的路径同步
+        let followed = submissions.iter().map(|(mid, _, _)| *mid).collect::<HashSet<_>>();
+        let unfollowed = submission::Entity::find()
+            .filter(submission::Column::FromFollowing.eq(true))
+            .all(connection)
+            .await?;
+        submissions.extend(
+            unfollowed
+                .into_iter()
+                .filter(|s| !followed.contains(&s.upper_id))
+                .map(|s| (s.upper_id, PathBuf::from(s.path), s.upper_name)),
+        );
+    }
+    Ok(submissions
+        .into_iter()
+        .map(|(mid, path, _)| (mid.to_string(), following_config.path.with_path(path)))
+        // 手动配置的投稿优先，此处跳过以免重复处理
+        .filter(|(mid, _)| !CONFIG.submission_list.contains_key(mid))
+        .collect())
+}
+
+/// 获取某个用户创建的所有收藏夹，返回收藏夹 id 与由 favorite_name 模板生成的保存路径
+/// 每轮执行时都会重新获取，因此新创建的收藏夹会被自动加入
+pub async fn refresh_user_favorite_list(
+    bili_client: &BiliClient,
+    mid: &str,
+    source: &SourceConfig,
+) -> Result<Vec<(String, SourceConfig)>> {
+    let folders = User::new(bili_client, mid.to_owned()).get_created_favorites().await?;
+    folders
+        .into_iter()
+        // 手动配置的收藏夹优先，此处跳过以免重复处理；空收藏夹没有可扫描的内容，同样跳过
+        .filter(|folder| folder.media_count > 0 && !CONFIG.favorite_list.contains_key(&folder.id.to_string()))
+        .map(|folder| {
+            let name = TEMPLATE.path_safe_render("favorite", &favorite_format_args(&folder))?;
+            Ok((folder.id.to_string(), source.with_path(source.path.join(name))))
+        })
+        .collect()
+}
+
+/// 获取当前账号“收藏和订阅”中的所有收藏夹与视频合集，返回对应的内容与保存路径
+pub async fn refresh_collected_list(bili_client: &BiliClient) -> Result<Vec<(Resource, SourceConfig)>> {
+    let mid = CONFIG
+        .credential
+        .load()
+        .as_deref()
+        .context("no credential found")?
+        .dedeuserid
+        .clone();
+    let folders = User::new(bili_client, mid)
+        .into_collected_stream()
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(folders
+        .into_iter()
+        // 空的收藏夹与合集没有可扫描的内容，跳过
+        .filter(|folder| folder.media_count > 0)
+        .filter_map(|folder| {
+            let resource = folder.resource()?;
+            // 手动配置的收藏夹与合集优先，此处跳过以免重复处理
+            let configured = match &resource {
+                Resource::Favorite(fid) => CONFIG.favorite_list.contains_key(fid),
+                Resource::Collection(collection_item) => CONFIG.collection_list.contains_key(collection_item),
+                _ => false,
+            };
+            let source = &CONFIG.collected.path;
+            (!configured).then(|| (resource, source.with_path(source.path.join(filenamify(&folder.title)))))
+        })
+        .collect())
+}
+
 /// 请求接口，获取视频列表中所有新添加的视频信息，将其写入数据库
 pub async fn refresh_video_list<'a>(
     video_list_model: &VideoListModelEnum,
     video_streams: Pin<Box<dyn Stream<Item = Result<VideoInfo>> + 'a + Send>>,
+    source: &SourceConfig,
+    full_rescan: bool,
     connection: &DatabaseConnection,
 ) -> Result<()> {
     video_list_model.log_refresh_video_start();
     let latest_row_at = video_list_model.get_latest_row_at().and_utc();
     let mut max_datetime = latest_row_at;
     let mut error = Ok(());
+    // 全量扫描或需要检测视频是否被移出时，必须获取完整的视频列表，不能在遇到旧视频时提前结束
+    let removal_policy = *source.removal_policy();
+    let walk_all = full_rescan || removal_policy != RemovalPolicy::Ignore;
+    if full_rescan {
+        info!("已开启全量扫描，将完整遍历视频列表并补全缺失的视频..");
+    }
+    let mut remote_bvids = HashSet::new();
     let mut video_streams = video_streams
         .take_while(|res| {
             match res {
@@ -72,18 +280,25 @@ pub async fn refresh_video_list<'a>(
                     if release_datetime > &max_datetime {
                         max_datetime = *release_datetime;
                     }
-                    futures::future::ready(release_datetime > &latest_row_at)
+                    futures::future::ready(release_datetime > &latest_row_at || walk_all)
                 }
             }
         })
         .filter_map(|res| futures::future::ready(res.ok()))
+        .filter(|v| {
+            if removal_policy != RemovalPolicy::Ignore {
+                remote_bvids.insert(v.bvid().to_owned());
+            }
+            // 全量扫描时旧视频也会被尝试插入，已存在的视频会被忽略，不会影响其下载状态
+            futures::future::ready(full_rescan || v.release_datetime() > &latest_row_at)
+        })
         .chunks(10);
     let mut count = 0;
     while let Some(videos_info) = video_streams.next().await {
         count += videos_info.len();
-        create_videos(videos_info, video_list_model, connection).await?;
+        create_videos(videos_info, video_list_model, source.content_filter(), connection).await?;
     }
-    // 如果获取视频分页过程中发生了错误，直接在此处返回，不更新 latest_row_at
+    // 如果获取视频分页过程中发生了错误，直接在此处返回，不更新 latest_row_at，也不检测被移出的视频
     error?;
     if max_datetime != latest_row_at {
         video_list_model
@@ -92,73 +307,914 @@ pub async fn refresh_video_list<'a>(
             .await?;
     }
     video_list_model.log_refresh_video_end(count);
+    if removal_policy != RemovalPolicy::Ignore {
+        reconcile_video_list(video_list_model, &remote_bvids, source, connection).await?;
+    }
+    Ok(())
+}
+
+/// 对比完整的视频列表，按照配置处理已经被移出视频列表的视频，重新加入视频列表的视频会被恢复
+pub async fn reconcile_video_list(
+    video_list_model: &VideoListModelEnum,
+    remote_bvids: &HashSet<String>,
+    source: &SourceConfig,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    let removal_policy = *source.removal_policy();
+    let videos = filter_source_video_pages(video_list_model.filter_expr(), connection).await?;
+    let (mut removed, mut restored) = (0, 0);
+    for (source_model, video_model, pages) in videos {
+        match (remote_bvids.contains(&video_model.bvid), source_model.removed_at) {
+            (false, None) => {
+                if let Err(e) = remove_video(source_model, video_model, pages, removal_policy, source, connection).await
+                {
+                    error!("处理被移出的视频失败：{e}");
+                } else {
+                    removed += 1;
+                }
+            }
+            (true, Some(_)) => {
+                if let Err(e) = restore_video(source_model, video_model, pages, connection).await {
+                    error!("恢复重新加入的视频失败：{e}");
+                } else {
+                    restored += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    if removed > 0 || restored > 0 {
+        info!(
+            "检测到 {} 个视频被移出，{} 个视频重新加入，移出的视频已按照 {:?} 策略处理",
+            removed, restored, removal_policy
+        );
+    }
+    Ok(())
+}
+
+/// 按照保留策略找出过期的视频，删除其本地文件并标记为过期，过期的视频不会再被下载
+pub async fn apply_retention(
+    video_list_model: &VideoListModelEnum,
+    source: &SourceConfig,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    let retention = source.retention();
+    if retention.is_empty() {
+        return Ok(());
+    }
+    let mut videos = filter_source_video_pages(
+        video_list_model
+            .filter_expr()
+            .and(video::Column::Valid.eq(true))
+            .and(video_source::Column::FilteredReason.is_null())
+            .and(video_source::Column::RemovedAt.is_null())
+            .and(video_source::Column::ExpiredAt.is_null()),
+        connection,
+    )
+    .await?;
+    // 尚未获取详情的视频可能缺少部分时间，依次使用收藏时间、发布时间与创建时间排序
+    let sort_key = |source_model: &video_source::Model, video_model: &video::Model| {
+        [source_model.favtime, video_model.pubtime, video_model.ctime]
+            .into_iter()
+            .find(|time| *time != DateTime::default())
+            .unwrap_or_default()
+    };
+    videos.sort_by_key(|(source_model, video_model, _)| std::cmp::Reverse(sort_key(source_model, video_model)));
+    let now = chrono::Utc::now().naive_utc();
+    let mut total_size = 0;
+    let mut expired = 0;
+    for (idx, (source_model, video_model, pages)) in videos.into_iter().enumerate() {
+        // 番剧的所有剧集共用同一个文件夹，大小的统计与删除都仅针对属于该集的文件
+        let files = if source_model.path.is_empty() {
+            Vec::new()
+        } else {
+            video_files(Path::new(&source_model.path), &video_model, &pages).await?
+        };
+        let mut size = 0;
+        for file in &files {
+            size += path_size(file).await?;
+        }
+        total_size += size;
+        let reason = if retention.max_count.is_some_and(|max_count| idx >= max_count) {
+            format!("超出保留数量 {}", idx + 1)
+        } else if retention
+            .max_age
+            .is_some_and(|max_age| sort_key(&source_model, &video_model) < now - chrono::Duration::days(max_age as i64))
+        {
+            format!("超出保留天数 {}", sort_key(&source_model, &video_model).date())
+        } else if retention
+            .max_size
+            .is_some_and(|max_size| total_size > max_size * 1024 * 1024)
+        {
+            format!("超出保留大小 {} MB", total_size / 1024 / 1024)
+        } else {
+            continue;
+        };
+        expired += 1;
+        if retention.dry_run {
+            info!("[dry run] 视频「{}」将因{}被删除", &video_model.name, reason);
+            continue;
+        }
+        for file in &files {
+            remove_path(file).await?;
+        }
+        if !source_model.path.is_empty() {
+            remove_video_dir_if_empty(Path::new(&source_model.path)).await?;
+        }
+        reset_dangling_links(&video_model, &source_model, connection).await?;
+        info!("视频「{}」因{}被删除", &video_model.name, reason);
+        let mut source_active_model: video_source::ActiveModel = source_model.into();
+        source_active_model.expired_at = Set(Some(now));
+        source_active_model.save(connection).await?;
+    }
+    if expired > 0 {
+        info!(
+            "{}共有 {} 个视频超出保留策略",
+            if retention.dry_run { "[dry run] " } else { "" },
+            expired
+        );
+    }
+    Ok(())
+}
+
+/// 按照策略处理被移出视频列表的视频
+async fn remove_video(
+    source_model: video_source::Model,
+    video_model: video::Model,
+    pages: Vec<page::Model>,
+    removal_policy: RemovalPolicy,
+    source: &SourceConfig,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    let video_path = Path::new(&source_model.path);
+    let files = if source_model.path.is_empty() {
+        Vec::new()
+    } else {
+        video_files(video_path, &video_model, &pages).await?
+    };
+    let mut source_active_model: video_source::ActiveModel = source_model.clone().into();
+    source_active_model.removed_at = Set(Some(chrono::Utc::now().naive_utc()));
+    match removal_policy {
+        RemovalPolicy::Ignore => return Ok(()),
+        RemovalPolicy::Keep => {}
+        RemovalPolicy::Archive => {
+            ensure!(
+                CONFIG.archive_path.is_absolute(),
+                "archive_path should be an absolute path, got {}",
+                CONFIG.archive_path.display()
+            );
+            // 归档时保留视频相对于视频列表所在文件夹的路径，避免不同视频列表之间的冲突
+            let relative = source
+                .path
+                .parent()
+                .and_then(|parent| video_path.strip_prefix(parent).ok())
+                .or_else(|| video_path.file_name().map(Path::new))
+                .context("invalid video path")?;
+            let archive_path = CONFIG.archive_path.join(relative);
+            for file in &files {
+                // 番剧剧集的文件位于 Season 1 中，归档时保留其相对路径
+                let target = if file == video_path {
+                    archive_path.clone()
+                } else {
+                    archive_path.join(file.strip_prefix(video_path).context("invalid file path")?)
+                };
+                move_path(file, &target).await?;
+            }
+            remove_video_dir_if_empty(video_path).await?;
+            reset_dangling_links(&video_model, &source_model, connection).await?;
+            source_active_model.archive_path = Set(Some(archive_path.to_string_lossy().to_string()));
+        }
+        RemovalPolicy::Delete => {
+            for file in &files {
+                remove_path(file).await?;
+            }
+            if !source_model.path.is_empty() {
+                remove_video_dir_if_empty(video_path).await?;
+            }
+            reset_dangling_links(&video_model, &source_model, connection).await?;
+            let txn = connection.begin().await?;
+            page::Entity::delete_many()
+                .filter(page::Column::VideoSourceId.eq(source_model.id))
+                .exec(&txn)
+                .await?;
+            video_source::Entity::delete_by_id(source_model.id).exec(&txn).await?;
+            // 视频不再属于任何视频来源时，一并删除视频本身的记录
+            let remaining = video_source::Entity::find()
+                .filter(video_source::Column::VideoId.eq(video_model.id))
+                .count(&txn)
+                .await?;
+            if remaining == 0 {
+                video::Entity::delete_by_id(video_model.id).exec(&txn).await?;
+            }
+            txn.commit().await?;
+            info!("视频「{}」已被移出，删除本地文件与记录", &video_model.name);
+            return Ok(());
+        }
+    }
+    source_active_model.update(connection).await?;
+    info!(
+        "视频「{}」已被移出，按照 {:?} 策略处理",
+        &video_model.name, removal_policy
+    );
+    Ok(())
+}
+
+/// 恢复重新加入视频列表的视频，已归档的内容会被移回原处
+async fn restore_video(
+    source_model: video_source::Model,
+    video_model: video::Model,
+    pages: Vec<page::Model>,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    if let Some(archive_path) = &source_model.archive_path {
+        let archive_path = Path::new(archive_path);
+        let video_path = Path::new(&source_model.path);
+        for file in video_files(archive_path, &video_model, &pages).await? {
+            let target = if file == archive_path {
+                video_path.to_path_buf()
+            } else {
+                video_path.join(file.strip_prefix(archive_path).context("invalid file path")?)
+            };
+            move_path(&file, &target).await?;
+        }
+        remove_video_dir_if_empty(archive_path).await?;
+    }
+    info!("视频「{}」重新加入视频列表，已恢复", &video_model.name);
+    let mut source_active_model: video_source::ActiveModel = source_model.into();
+    source_active_model.removed_at = Set(None);
+    source_active_model.archive_path = Set(None);
+    source_active_model.update(connection).await?;
+    Ok(())
+}
+
+/// 使用当前的过滤规则重新检查视频列表中被过滤的视频，规则放宽或移除后，不再被过滤的视频会在之后正常获取详情与下载
+pub async fn refilter_videos(
+    video_list_model: &VideoListModelEnum,
+    content_filter: &ContentFilter,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    let videos = filter_source_video_pages(
+        video_list_model
+            .filter_expr()
+            .and(video_source::Column::FilteredReason.is_not_null()),
+        connection,
+    )
+    .await?;
+    for (source_model, video_model, pages) in videos {
+        let video_active_model = video_model.clone().into_active_model();
+        // 尚未在该视频来源中获取详情的视频仅检查基本信息，详情中的字段会在获取详情时检查
+        let filtered_reason = if pages.is_empty() {
+            content_filter.check_video(&video_active_model)
+        } else {
+            let tag_names = video_model
+                .tags
+                .clone()
+                .and_then(|tags| serde_json::from_value::<Vec<String>>(tags).ok())
+                .unwrap_or_default();
+            let duration = pages.iter().map(|page| page.duration).sum();
+            content_filter.check_detail(&video_active_model, &tag_names, duration)
+        };
+        if filtered_reason == source_model.filtered_reason {
+            continue;
+        }
+        match &filtered_reason {
+            Some(reason) => info!("视频「{}」被过滤：{}", &video_model.name, reason),
+            None => info!("视频「{}」不再被过滤，将在之后下载", &video_model.name),
+        }
+        let mut source_active_model: video_source::ActiveModel = source_model.into();
+        source_active_model.filtered_reason = Set(filtered_reason);
+        source_active_model.save(connection).await?;
+    }
     Ok(())
 }
 
 /// 筛选出所有未获取到全部信息的视频，尝试补充其详细信息
+/// 视频的详情与分页由所有视频来源共享，已经在其它视频来源中获取过详情的视频不会重复请求
 pub async fn fetch_video_details(
     bili_client: &BiliClient,
     video_list_model: &VideoListModelEnum,
+    source: &SourceConfig,
     connection: &DatabaseConnection,
 ) -> Result<()> {
     video_list_model.log_fetch_video_start();
-    let videos_model = filter_unfilled_videos(video_list_model.filter_expr(), connection).await?;
-    for video_model in videos_model {
-        let video = Video::new(bili_client, video_model.bvid.clone());
-        let info: Result<_> = async { Ok((video.get_tags().await?, video.get_view_info().await?)) }.await;
-        match info {
-            Err(e) => {
-                error!(
-                    "获取视频 {} - {} 的详细信息失败，错误为：{}",
-                    &video_model.bvid, &video_model.name, e
-                );
-                if let Some(BiliError::RequestFailed(-404, _)) = e.downcast_ref::<BiliError>() {
-                    let mut video_active_model: bili_sync_entity::video::ActiveModel = video_model.into();
-                    video_active_model.valid = Set(false);
-                    video_active_model.save(connection).await?;
+    let videos = filter_unfilled_videos(video_list_model.filter_expr(), connection).await?;
+    // 番剧的所有剧集信息在一次请求中返回，缓存已经获取的剧集，避免为每一集重复请求
+    let mut seasons = Vec::new();
+    for (source_model, video_model) in videos {
+        let shared_pages = if video_model.single_page.is_some() {
+            let mut pages = filter_video_pages(video_model.id, connection).await?;
+            pages.sort_by_key(|page| page.pid);
+            pages.dedup_by_key(|page| page.pid);
+            pages
+        } else {
+            Vec::new()
+        };
+        let detail = if shared_pages.is_empty() {
+            match fetch_video_detail(bili_client, &video_model, &mut seasons).await {
+                Ok(detail) => Some(detail),
+                Err(e) => {
+                    error!(
+                        "获取视频 {} - {} 的详细信息失败，错误为：{}",
+                        &video_model.bvid, &video_model.name, e
+                    );
+                    if let Some(BiliError::RequestFailed(-404, _)) = e.downcast_ref::<BiliError>() {
+                        let mut video_active_model: video::ActiveModel = video_model.into();
+                        video_active_model.valid = Set(false);
+                        video_active_model.save(connection).await?;
+                    }
+                    continue;
                 }
             }
-            Ok((tags, mut view_info)) => {
+        } else {
+            None
+        };
+        let txn = connection.begin().await?;
+        let (video_model, duration) = match detail {
+            Some((tags, mut view_info)) => {
                 let VideoInfo::Detail { pages, .. } = &mut view_info else {
                     unreachable!()
                 };
                 let pages = std::mem::take(pages);
-                let pages_len = pages.len();
-                let txn = connection.begin().await?;
+                let duration = pages.iter().map(|page| page.duration).sum();
+                // 番剧的每一集都作为剧集中的一集存放，因此固定使用多页视频的目录结构
+                let single_page = pages.len() == 1 && video_model.ep_id.is_none();
                 // 将分页信息写入数据库
-                create_pages(pages, &video_model, &txn).await?;
+                create_pages(pages, &source_model, &txn).await?;
                 let mut video_active_model = view_info.into_detail_model(video_model);
-                video_list_model.set_relation_id(&mut video_active_model);
-                video_active_model.single_page = Set(Some(pages_len == 1));
-                video_active_model.tags = Set(Some(serde_json::to_value(tags)?));
-                video_active_model.save(&txn).await?;
-                txn.commit().await?;
+                video_active_model.single_page = Set(Some(single_page));
+                video_active_model.tags = Set(Some(tags));
+                let now = chrono::Utc::now().naive_utc();
+                video_active_model.pages_checked_at = Set(Some(now));
+                video_active_model.metadata_checked_at = Set(Some(now));
+                (video_active_model.update(&txn).await?, duration)
+            }
+            None => {
+                copy_pages(&shared_pages, &source_model, &txn).await?;
+                (video_model, shared_pages.iter().map(|page| page.duration).sum())
             }
         };
+        let tag_names = video_model
+            .tags
+            .clone()
+            .and_then(|tags| serde_json::from_value::<Vec<String>>(tags).ok())
+            .unwrap_or_default();
+        let filtered_reason =
+            source
+                .content_filter()
+                .check_detail(&video_model.clone().into_active_model(), &tag_names, duration);
+        if let Some(reason) = &filtered_reason {
+            info!("视频「{}」被过滤：{}", &video_model.name, reason);
+        }
+        let mut source_active_model: video_source::ActiveModel = source_model.into();
+        // 视频列表中没有收藏时间的视频使用发布时间填充
+        if *source_active_model.favtime.as_ref() == DateTime::default() {
+            source_active_model.favtime = Set(video_model.pubtime);
+        }
+        source_active_model.filtered_reason = Set(filtered_reason);
+        source_active_model.save(&txn).await?;
+        txn.commit().await?;
     }
     video_list_model.log_fetch_video_end();
     Ok(())
 }
 
+/// 请求视频的详情，返回视频的标签与包含所有分页的详情，番剧剧集优先从 seasons 中已经获取的剧集查找
+async fn fetch_video_detail(
+    bili_client: &BiliClient,
+    video_model: &video::Model,
+    seasons: &mut Vec<Season>,
+) -> Result<(serde_json::Value, VideoInfo)> {
+    match video_model.ep_id {
+        // 番剧剧集的详情需要从 pgc 接口获取，此时使用剧集的风格作为标签
+        Some(ep_id) => {
+            let season = match seasons.iter().position(|season| season.contains(ep_id)) {
+                Some(idx) => &seasons[idx],
+                None => {
+                    let bangumi_item = BangumiItem::Episode(ep_id.to_string());
+                    let season = Bangumi::new(bili_client, &bangumi_item).get_season_detail().await?;
+                    seasons.push(season);
+                    &seasons[seasons.len() - 1]
+                }
+            };
+            let (styles, view_info) = season.episode_detail(ep_id)?;
+            Ok((serde_json::to_value(styles)?, view_info))
+        }
+        None => {
+            let video = Video::new(bili_client, video_model.bvid.clone())?;
+            Ok((
+                serde_json::to_value(video.get_tags().await?)?,
+                video.get_view_info().await?,
+            ))
+        }
+    }
+}
+
+/// 重新获取近期发布视频的分页列表，将新增的分页写入数据库，使其在之后被下载
+pub async fn recheck_video_pages(
+    bili_client: &BiliClient,
+    video_list_model: &VideoListModelEnum,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    let now = chrono::Utc::now().naive_utc();
+    let videos = filter_recheck_videos(
+        video_list_model.filter_expr(),
+        now - chrono::Duration::days(CONFIG.page_recheck.max_age as i64),
+        now - chrono::Duration::seconds(CONFIG.page_recheck.interval as i64),
+        connection,
+    )
+    .await?;
+    for video_model in videos {
+        let (bvid, name) = (video_model.bvid.clone(), video_model.name.clone());
+        if let Err(e) = recheck_pages(bili_client, video_model, now, connection).await {
+            error!("检查视频 {} - {} 的分页失败，错误为：{}", bvid, name, e);
+        }
+    }
+    Ok(())
+}
+
+/// 新增的分页会被写入视频所在的所有视频来源中
+async fn recheck_pages(
+    bili_client: &BiliClient,
+    video_model: video::Model,
+    now: DateTime,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    let remote_pages = Video::new(bili_client, video_model.bvid.clone())?.get_pages().await?;
+    let sources = filter_video_sources(video_model.id, connection).await?;
+    let new_count = remote_pages
+        .iter()
+        .filter(|remote| {
+            sources
+                .iter()
+                .flat_map(|(_, pages)| pages)
+                .all(|local| local.pid != remote.page)
+        })
+        .count();
+    let mut video_active_model: video::ActiveModel = video_model.clone().into();
+    video_active_model.pages_checked_at = Set(Some(now));
+    if new_count == 0 {
+        video_active_model.save(connection).await?;
+        return Ok(());
+    }
+    let switch_to_multi = video_model.single_page == Some(true);
+    // 迁移文件时记录已经完成的移动与需要删除的文件，写入数据库失败时移回原处，成功后再删除
+    let (mut moved, mut obsolete) = (Vec::new(), Vec::new());
+    let res = async {
+        let txn = connection.begin().await?;
+        for (source_model, pages) in sources {
+            // 尚未创建分页的视频来源会在获取详情时复用新的分页
+            if pages.is_empty() {
+                continue;
+            }
+            let mut status = VideoStatus::from(source_model.download_status);
+            // 新增的分页需要重新执行分 P 下载的子任务
+            status.reset(4);
+            if switch_to_multi {
+                // 单页视频变为多页视频后，需要迁移已下载的文件，并补充多页视频独有的封面与 tvshow.nfo
+                switch_to_multi_page(&pages, &mut moved, &mut obsolete, &txn).await?;
+                status.reset(0);
+                status.reset(1);
+            }
+            create_pages(remote_pages.clone(), &source_model, &txn).await?;
+            let mut source_active_model: video_source::ActiveModel = source_model.into();
+            source_active_model.download_status = Set(status.into());
+            source_active_model.save(&txn).await?;
+        }
+        if switch_to_multi {
+            video_active_model.single_page = Set(Some(false));
+        }
+        video_active_model.save(&txn).await?;
+        txn.commit().await?;
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+    if let Err(e) = res {
+        undo_moves(moved).await;
+        return Err(e);
+    }
+    for file in obsolete {
+        if let Err(e) = fs::remove_file(&file).await {
+            warn!("删除 {} 失败：{:#}", file.display(), e);
+        }
+    }
+    info!("视频「{}」新增了 {} 个分页，将在之后下载", &video_model.name, new_count);
+    Ok(())
+}
+
+/// 重新获取已下载视频的元数据，标题、UP 主昵称等发生变化时重新生成 nfo，并在之后下载时按照新的命名移动本地文件
+pub async fn refresh_video_metadata(
+    bili_client: &BiliClient,
+    video_list_model: &VideoListModelEnum,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    let now = chrono::Utc::now().naive_utc();
+    let videos = filter_refresh_videos(
+        video_list_model.filter_expr(),
+        now - chrono::Duration::seconds(CONFIG.metadata_refresh.interval as i64),
+        connection,
+    )
+    .await?;
+    for video_model in videos {
+        let (bvid, name) = (video_model.bvid.clone(), video_model.name.clone());
+        if let Err(e) = refresh_metadata(bili_client, video_model, now, connection).await {
+            error!("刷新视频 {} - {} 的元数据失败，错误为：{}", bvid, name, e);
+        }
+    }
+    Ok(())
+}
+
+/// 元数据由所有视频来源共享，发生变化时会重置视频所在的所有视频来源中 nfo 的下载状态
+async fn refresh_metadata(
+    bili_client: &BiliClient,
+    video_model: video::Model,
+    now: DateTime,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    let video = Video::new(bili_client, video_model.bvid.clone())?;
+    let tags = serde_json::to_value(video.get_tags().await?)?;
+    let view_info = video.get_view_info().await?;
+    let (source_models, pages): (Vec<_>, Vec<_>) = filter_video_sources(video_model.id, connection)
+        .await?
+        .into_iter()
+        .unzip();
+    let pages = pages.into_iter().flatten().collect::<Vec<_>>();
+    let (mut new_video, mut new_pages) = (video_model.clone(), pages.clone());
+    view_info.apply_metadata(&mut new_video, &mut new_pages);
+    new_video.tags = Some(tags);
+    new_video.metadata_checked_at = Some(now);
+    let changed = new_pages != pages || {
+        let mut old_video = video_model.clone();
+        old_video.metadata_checked_at = Some(now);
+        new_video != old_video
+    };
+    if !changed {
+        let mut video_active_model: video::ActiveModel = video_model.into();
+        video_active_model.metadata_checked_at = Set(Some(now));
+        video_active_model.save(connection).await?;
+        return Ok(());
+    }
+    // 元数据变化后需要重新生成视频与分页的 nfo，文件的移动在下载时进行
+    let txn = connection.begin().await?;
+    for mut source_model in source_models {
+        let mut status = VideoStatus::from(source_model.download_status);
+        status.reset(1);
+        status.reset(4);
+        source_model.download_status = status.into();
+        video_source::ActiveModel::from(source_model)
+            .reset_all()
+            .update(&txn)
+            .await?;
+    }
+    for mut page_model in new_pages {
+        let mut page_status = PageStatus::from(page_model.download_status);
+        page_status.reset(2);
+        page_model.download_status = page_status.into();
+        page::ActiveModel::from(page_model).reset_all().update(&txn).await?;
+    }
+    video::ActiveModel::from(new_video).reset_all().update(&txn).await?;
+    txn.commit().await?;
+    info!("视频「{}」的元数据发生变化，将在之后更新本地文件", &video_model.name);
+    Ok(())
+}
+
+/// 检查近期发布视频中已下载分页的视频流，出现更好的视频流时重置分页的下载状态，使其在之后重新下载
+pub async fn upgrade_video_quality(
+    bili_client: &BiliClient,
+    video_list_model: &VideoListModelEnum,
+    source: &SourceConfig,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    let now = chrono::Utc::now().naive_utc();
+    let videos = filter_upgrade_pages(
+        video_list_model.filter_expr(),
+        now - chrono::Duration::days(CONFIG.quality_upgrade.max_age as i64),
+        now - chrono::Duration::seconds(CONFIG.quality_upgrade.interval as i64),
+        connection,
+    )
+    .await?;
+    for (source_model, video_model, pages) in videos {
+        if let Err(e) = upgrade_pages(bili_client, source, source_model, &video_model, pages, now, connection).await {
+            error!(
+                "检查视频 {} - {} 的视频流失败，错误为：{}",
+                &video_model.bvid, &video_model.name, e
+            );
+        }
+    }
+    Ok(())
+}
+
+/// 视频流的选择受视频来源的筛选设置影响，因此每个视频来源中的分页单独检查
+async fn upgrade_pages(
+    bili_client: &BiliClient,
+    source: &SourceConfig,
+    source_model: video_source::Model,
+    video_model: &video::Model,
+    pages: Vec<page::Model>,
+    now: DateTime,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    let (filter_option, output) = (source.filter_option(), source.output());
+    let bili_video = Video::new(bili_client, video_model.bvid.clone())?;
+    let mut upgraded = false;
+    let mut page_models = Vec::with_capacity(pages.len());
+    for mut page_model in pages {
+        let page_info = PageInfo {
+            cid: page_model.cid,
+            ep_id: video_model.ep_id,
+            ..Default::default()
+        };
+        let mut analyzer = bili_video.get_page_analyzer(&page_info).await?;
+        let quality = if output.format == OutputFormat::Audio {
+            analyzer.best_audio_stream(filter_option)?.quality()
+        } else {
+            analyzer.best_stream(filter_option)?.quality()
+        };
+        if quality.is_better_than(&StreamQuality::from(&page_model), filter_option) {
+            let mut status = PageStatus::from(page_model.download_status);
+            status.reset(1);
+            if output.format != OutputFormat::Mp4 && !output.sidecar {
+                // 嵌入的封面、弹幕与字幕在合并后已被删除，需要重新下载
+                status.reset(0);
+                status.reset(3);
+                status.reset(4);
+            }
+            page_model.download_status = status.into();
+            // 之前的视频流遗留的临时文件与 `.part` 文件不能用于新的视频流
+            if let Some(path) = &page_model.path {
+                remove_partial_files(Path::new(path)).await?;
+            }
+            upgraded = true;
+            info!(
+                "视频「{}」第 {} 页出现了更好的视频流，将在之后重新下载",
+                &video_model.name, page_model.pid
+            );
+        }
+        page_model.quality_checked_at = Some(now);
+        page_models.push(page_model);
+    }
+    let txn = connection.begin().await?;
+    for page_model in page_models {
+        page::ActiveModel::from(page_model).reset_all().update(&txn).await?;
+    }
+    if upgraded {
+        let mut status = VideoStatus::from(source_model.download_status);
+        status.reset(4);
+        let mut source_active_model: video_source::ActiveModel = source_model.into();
+        source_active_model.download_status = Set(status.into());
+        source_active_model.save(&txn).await?;
+    }
+    txn.commit().await?;
+    Ok(())
+}
+
+/// 删除分页下载过程中遗留的临时文件与 `.part` 文件，与 fetch_page_video、fetch_page_audio 中的命名保持一致
+async fn remove_partial_files(page_path: &Path) -> Result<()> {
+    remove_parts(page_path).await?;
+    for extension in ["tmp_video", "tmp_audio"] {
+        let tmp_path = page_path.with_extension(extension);
+        remove_parts(&tmp_path).await?;
+        if fs::try_exists(&tmp_path).await? {
+            remove_path(&tmp_path).await?;
+        }
+    }
+    Ok(())
+}
+
+/// 视频或分页的命名发生变化时（例如刷新元数据后），将已下载的文件移动到新的路径，与 download_page 中的命名保持一致
+/// 移动文件或写入数据库失败时，已经移动的文件会被移回原处，source_model 与 pages 保持不变
+async fn relocate_video(
+    source: &SourceConfig,
+    video_model: &video::Model,
+    source_model: &mut video_source::Model,
+    pages: &mut [page::Model],
+    base_path: &Path,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    if source_model.path.is_empty() {
+        return Ok(());
+    }
+    let (mut new_source_model, mut new_pages) = (source_model.clone(), pages.to_vec());
+    let mut moved = Vec::new();
+    let res = move_video_files(
+        source,
+        video_model,
+        &mut new_source_model,
+        &mut new_pages,
+        base_path,
+        &mut moved,
+        connection,
+    )
+    .await;
+    if res.is_ok() {
+        *source_model = new_source_model;
+        pages.clone_from_slice(&new_pages);
+        return res;
+    }
+    undo_moves(moved).await;
+    res
+}
+
+/// 按照相反的顺序将已经移动的文件移回原处，用于写入数据库失败时的回滚
+async fn undo_moves(moved: Vec<(PathBuf, PathBuf)>) {
+    for (from, to) in moved.into_iter().rev() {
+        if let Err(e) = move_path(&to, &from).await {
+            error!("将 {} 移回 {} 失败：{:#}", to.display(), from.display(), e);
+        }
+    }
+}
+
+/// 移动视频的文件并更新数据库中的路径，moved 中按顺序记录了已经完成的移动
+async fn move_video_files(
+    source: &SourceConfig,
+    video_model: &video::Model,
+    source_model: &mut video_source::Model,
+    pages: &mut [page::Model],
+    base_path: &Path,
+    moved: &mut Vec<(PathBuf, PathBuf)>,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    let is_single_page = video_model.single_page.context("single_page is null")?;
+    // 单页视频与番剧的剧集与其它视频共用文件夹，需要逐个移动属于该视频的文件
+    let shared_dir = is_single_page || video_model.ep_id.is_some();
+    let old_path = PathBuf::from(&source_model.path);
+    let path_changed = old_path != base_path;
+    if path_changed && !shared_dir && fs::try_exists(&old_path).await? {
+        // 多页视频独占整个文件夹，直接移动文件夹即可
+        ensure!(
+            !fs::try_exists(base_path).await?,
+            "target path {} already exists",
+            base_path.display()
+        );
+        move_path(&old_path, base_path).await?;
+        moved.push((old_path.clone(), base_path.to_path_buf()));
+    }
+    let txn = connection.begin().await?;
+    for page_model in pages.iter_mut() {
+        let Some(page_path) = page_model.path.clone() else {
+            continue;
+        };
+        let base_name = TEMPLATE.path_safe_render(
+            &source.page_template(),
+            &page_format_args(video_model, source_model, page_model),
+        )?;
+        let (dir, new_stem) = if is_single_page {
+            (base_path.to_path_buf(), base_name)
+        } else {
+            (
+                base_path.join("Season 1"),
+                format!("{} - S01E{:0>2}", base_name, page_model.pid),
+            )
+        };
+        let old_page_path = Path::new(&page_path);
+        let old_stem = old_page_path
+            .file_stem()
+            .context("invalid page path")?
+            .to_string_lossy()
+            .to_string();
+        // 多页视频的文件夹已经整体移动，此处仅需要处理文件名的变化
+        let old_dir = if shared_dir {
+            old_page_path.parent().context("invalid page path")?.to_path_buf()
+        } else {
+            dir.clone()
+        };
+        // 保持原有的文件格式，输出格式的变化仅影响之后下载的分页
+        let extension = old_page_path.extension().unwrap_or_default().to_string_lossy();
+        let new_page_path = dir.join(format!("{}.{}", new_stem, extension));
+        if old_page_path == new_page_path {
+            continue;
+        }
+        rename_page_files(&old_dir, &old_stem, &dir, &new_stem, moved).await?;
+        page_model.previous_path = Some(page_path);
+        page_model.path = Some(new_page_path.to_string_lossy().to_string());
+        page::ActiveModel::from(page_model.clone())
+            .reset_all()
+            .update(&txn)
+            .await?;
+    }
+    if path_changed {
+        if shared_dir {
+            remove_video_dir_if_empty(&old_path).await?;
+        }
+        source_model.previous_path = Some(std::mem::replace(
+            &mut source_model.path,
+            base_path.to_string_lossy().to_string(),
+        ));
+        video_source::ActiveModel::from(source_model.clone())
+            .reset_all()
+            .update(&txn)
+            .await?;
+        info!("视频「{}」的命名发生变化，已移动本地文件", &video_model.name);
+    }
+    txn.commit().await?;
+    Ok(())
+}
+
+/// 将 old_dir 中属于某个分页的文件重命名到 new_dir 中，与 download_page 中的命名保持一致
+async fn rename_page_files(
+    old_dir: &Path,
+    old_stem: &str,
+    new_dir: &Path,
+    new_stem: &str,
+    moved: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<()> {
+    if !fs::try_exists(old_dir).await? {
+        return Ok(());
+    }
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(old_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        files.push((entry.path(), entry.file_name().to_string_lossy().to_string()));
+    }
+    for (file, file_name) in files {
+        if let Some(rest) = page_file_suffix(&file_name, old_stem) {
+            let target = new_dir.join(format!("{}{}", new_stem, rest));
+            move_path(&file, &target).await?;
+            moved.push((file, target));
+        }
+    }
+    Ok(())
+}
+
+/// 将单页视频已下载的文件迁移到多页视频的目录结构中，与 download_page 中的命名保持一致
+/// moved 中按顺序记录已经完成的移动，obsolete 中记录多页视频不再需要的文件，由调用方在写入数据库成功后删除
+async fn switch_to_multi_page(
+    pages: &[page::Model],
+    moved: &mut Vec<(PathBuf, PathBuf)>,
+    obsolete: &mut Vec<PathBuf>,
+    connection: &DatabaseTransaction,
+) -> Result<()> {
+    for page_model in pages {
+        let Some(path) = page_model.path.as_deref().map(Path::new) else {
+            continue;
+        };
+        let (Some(dir), Some(base_name)) = (path.parent(), path.file_stem()) else {
+            continue;
+        };
+        let base_name = base_name.to_string_lossy();
+        let extension = path.extension().unwrap_or_default().to_string_lossy();
+        let season_path = dir.join("Season 1");
+        let episode_name = format!("{} - S01E{:0>2}", base_name, page_model.pid);
+        let mut files = Vec::new();
+        if fs::try_exists(dir).await? {
+            let mut entries = fs::read_dir(dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                files.push((entry.path(), entry.file_name().to_string_lossy().to_string()));
+            }
+        }
+        for (file, file_name) in files {
+            let target = match page_file_suffix(&file_name, &base_name) {
+                Some("-poster.jpg") => season_path.join(format!("{}-thumb.jpg", episode_name)),
+                // 多页视频的 fanart 由视频统一下载，单集的 nfo 格式也与单页视频不同，删除后重新生成
+                Some("-fanart.jpg" | ".nfo") => {
+                    obsolete.push(file);
+                    continue;
+                }
+                Some(suffix) => season_path.join(format!("{}{}", episode_name, suffix)),
+                None => continue,
+            };
+            move_path(&file, &target).await?;
+            moved.push((file, target));
+        }
+        let mut status = PageStatus::from(page_model.download_status);
+        status.reset(2);
+        let mut page_active_model: page::ActiveModel = page_model.clone().into();
+        page_active_model.download_status = Set(status.into());
+        page_active_model.path = Set(Some(
+            season_path
+                .join(format!("{}.{}", episode_name, extension))
+                .to_string_lossy()
+                .to_string(),
+        ));
+        page_active_model.save(connection).await?;
+    }
+    Ok(())
+}
+
 /// 下载所有未处理成功的视频
 pub async fn download_unprocessed_videos(
     bili_client: &BiliClient,
     video_list_model: &VideoListModelEnum,
+    source: &SourceConfig,
     connection: &DatabaseConnection,
 ) -> Result<()> {
     video_list_model.log_download_video_start();
     let semaphore = Semaphore::new(CONFIG.concurrent_limit.video);
-    let downloader = Downloader::new(bili_client.client.clone());
+    let downloader = Downloader::new(
+        bili_client.client.clone(),
+        !matches!(video_list_model, VideoListModelEnum::Adhoc(_)),
+    );
     let unhandled_videos_pages = filter_unhandled_video_pages(video_list_model.filter_expr(), connection).await?;
     let mut assigned_upper = HashSet::new();
     let tasks = unhandled_videos_pages
         .into_iter()
-        .map(|(video_model, pages_model)| {
+        .map(|(source_model, video_model, pages_model)| {
             let should_download_upper = !assigned_upper.contains(&video_model.upper_id);
             assigned_upper.insert(video_model.upper_id);
             download_video_pages(
                 bili_client,
                 video_list_model,
+                source,
+                source_model,
                 video_model,
                 pages_model,
                 connection,
@@ -168,17 +1224,20 @@ pub async fn download_unprocessed_videos(
             )
         })
         .collect::<FuturesUnordered<_>>();
-    let mut download_aborted = false;
+    let (mut download_aborted, mut download_paused) = (false, false);
     let mut stream = tasks
-        // 触发风控时设置 download_aborted 标记并终止流
+        // 触发风控或离开允许下载的时间段时设置对应的标记并终止流，其它错误仅记录日志
         .take_while(|res| {
-            if res
-                .as_ref()
-                .is_err_and(|e| e.downcast_ref::<DownloadAbortError>().is_some())
-            {
-                download_aborted = true;
+            if let Err(e) = res {
+                if e.downcast_ref::<DownloadAbortError>().is_some() {
+                    download_aborted = true;
+                } else if e.downcast_ref::<DownloadPausedError>().is_some() {
+                    download_paused = true;
+                } else {
+                    error!("处理视频时遇到错误：{:#}", e);
+                }
             }
-            futures::future::ready(!download_aborted)
+            futures::future::ready(!download_aborted && !download_paused)
         })
         // 过滤掉没有触发风控的普通 Err，只保留正确返回的 Model
         .filter_map(|res| futures::future::ready(res.ok()))
@@ -189,6 +1248,8 @@ pub async fn download_unprocessed_videos(
     }
     if download_aborted {
         error!("下载触发风控，已终止所有任务，等待下一轮执行");
+    } else if download_paused {
+        info!("已离开允许下载的时间段，暂停下载，未完成的文件将在下一个时间段继续下载");
     }
     video_list_model.log_download_video_end();
     Ok(())
@@ -198,25 +1259,55 @@ pub async fn download_unprocessed_videos(
 pub async fn download_video_pages(
     bili_client: &BiliClient,
     video_list_model: &VideoListModelEnum,
+    source: &SourceConfig,
+    mut source_model: video_source::Model,
     video_model: video::Model,
-    pages: Vec<page::Model>,
+    mut pages: Vec<page::Model>,
     connection: &DatabaseConnection,
     semaphore: &Semaphore,
     downloader: &Downloader,
     should_download_upper: bool,
-) -> Result<video::ActiveModel> {
+) -> Result<video_source::ActiveModel> {
     let _permit = semaphore.acquire().await.context("acquire semaphore failed")?;
-    let mut status = VideoStatus::from(video_model.download_status);
+    let mut status = VideoStatus::from(source_model.download_status);
     let seprate_status = status.should_run();
-    let base_path = video_list_model
-        .path()
-        .join(TEMPLATE.path_safe_render("video", &video_format_args(&video_model))?);
+    let base_path = match video_list_model {
+        // 单独下载的视频保存在首次下载时指定的位置，之后指定其它的输出目录时不移动已有的文件
+        VideoListModelEnum::Adhoc(_) if !source_model.path.is_empty() => PathBuf::from(&source_model.path),
+        _ => {
+            let base_path = video_list_model.path().join(TEMPLATE.path_safe_render(
+                &source.video_template(),
+                &video_format_args(&video_model, &source_model),
+            )?);
+            match relocate_video(
+                source,
+                &video_model,
+                &mut source_model,
+                &mut pages,
+                &base_path,
+                connection,
+            )
+            .await
+            {
+                Ok(_) => base_path,
+                // 移动失败时本轮继续使用原有的路径，下一轮重新尝试移动
+                Err(e) => {
+                    error!(
+                        "移动视频「{}」的本地文件失败：{:#}，本轮继续使用原有的路径",
+                        &video_model.name, e
+                    );
+                    PathBuf::from(&source_model.path)
+                }
+            }
+        }
+    };
     let upper_id = video_model.upper_id.to_string();
     let base_upper_path = &CONFIG
         .upper_path
         .join(upper_id.chars().next().context("upper_id is empty")?.to_string())
         .join(upper_id);
     let is_single_page = video_model.single_page.context("single_page is null")?;
+    let album = video_list_model.name();
     // 对于单页视频，page 的下载已经足够
     // 对于多页视频，page 下载仅包含了分集内容，需要额外补上视频的 poster 的 tvshow.nfo
     let tasks: Vec<Pin<Box<dyn Future<Output = Result<()>> + Send>>> = vec![
@@ -232,6 +1323,8 @@ pub async fn download_video_pages(
         Box::pin(generate_video_nfo(
             seprate_status[1] && !is_single_page,
             &video_model,
+            &source_model,
+            source.nfo_time_type(),
             base_path.join("tvshow.nfo"),
         )),
         // 下载 Up 主头像
@@ -245,13 +1338,17 @@ pub async fn download_video_pages(
         Box::pin(generate_upper_nfo(
             seprate_status[3] && should_download_upper,
             &video_model,
+            &source_model,
             base_upper_path.join("person.nfo"),
         )),
         // 分发并执行分 P 下载的任务
         Box::pin(dispatch_download_page(
             seprate_status[4],
             bili_client,
+            source,
+            &album,
             &video_model,
+            &source_model,
             pages,
             connection,
             downloader,
@@ -260,6 +1357,9 @@ pub async fn download_video_pages(
     ];
     let tasks: FuturesOrdered<_> = tasks.into_iter().collect();
     let results: Vec<Result<()>> = tasks.collect().await;
+    if is_download_paused(&results) {
+        bail!(DownloadPausedError());
+    }
     status.update_status(&results);
     results
         .iter()
@@ -274,17 +1374,25 @@ pub async fn download_video_pages(
             return Err(e);
         }
     }
-    let mut video_active_model: video::ActiveModel = video_model.into();
-    video_active_model.download_status = Set(status.into());
-    video_active_model.path = Set(base_path.to_string_lossy().to_string());
-    Ok(video_active_model)
+    // 移动文件或升级画质都可能使其它视频来源中的符号链接失效
+    if let Err(e) = reset_dangling_links(&video_model, &source_model, connection).await {
+        error!("检查视频「{}」失效的符号链接失败：{:#}", &video_model.name, e);
+    }
+    let mut source_active_model: video_source::ActiveModel = source_model.into();
+    source_active_model.download_status = Set(status.into());
+    source_active_model.path = Set(base_path.to_string_lossy().to_string());
+    Ok(source_active_model)
 }
 
 /// 分发并执行分页下载任务，当且仅当所有分页成功下载或达到最大重试次数时返回 Ok，否则根据失败原因返回对应的错误
+#[allow(clippy::too_many_arguments)]
 pub async fn dispatch_download_page(
     should_run: bool,
     bili_client: &BiliClient,
+    source: &SourceConfig,
+    album: &str,
     video_model: &video::Model,
+    source_model: &video_source::Model,
     pages: Vec<page::Model>,
     connection: &DatabaseConnection,
     downloader: &Downloader,
@@ -293,21 +1401,27 @@ pub async fn dispatch_download_page(
     if !should_run {
         return Ok(());
     }
+    let downloaded_pages = downloaded_pages(video_model, source_model, connection).await?;
     let child_semaphore = Semaphore::new(CONFIG.concurrent_limit.page);
     let tasks = pages
         .into_iter()
         .map(|page_model| {
+            let downloaded = downloaded_pages.get(&page_model.pid);
             download_page(
                 bili_client,
+                source,
+                album,
                 video_model,
+                source_model,
                 page_model,
+                downloaded,
                 &child_semaphore,
                 downloader,
                 base_path,
             )
         })
         .collect::<FuturesUnordered<_>>();
-    let (mut download_aborted, mut error_occurred) = (false, false);
+    let (mut download_aborted, mut download_paused, mut error_occurred) = (false, false, false);
     let mut stream = tasks
         .take_while(|res| {
             match res {
@@ -324,11 +1438,13 @@ pub async fn dispatch_download_page(
                 Err(e) => {
                     if e.downcast_ref::<DownloadAbortError>().is_some() {
                         download_aborted = true;
+                    } else if e.downcast_ref::<DownloadPausedError>().is_some() {
+                        download_paused = true;
                     }
                 }
             }
-            // 仅在发生风控时终止流，其它情况继续执行
-            futures::future::ready(!download_aborted)
+            // 仅在发生风控或离开允许下载的时间段时终止流，其它情况继续执行
+            futures::future::ready(!download_aborted && !download_paused)
         })
         .filter_map(|res| futures::future::ready(res.ok()))
         .chunks(10);
@@ -339,6 +1455,9 @@ pub async fn dispatch_download_page(
         error!("下载视频「{}」的分页时触发风控，将异常向上传递..", &video_model.name);
         bail!(DownloadAbortError());
     }
+    if download_paused {
+        bail!(DownloadPausedError());
+    }
     if error_occurred {
         error!(
             "下载视频「{}」的分页时出现错误，将在下一轮尝试重新处理",
@@ -349,11 +1468,101 @@ pub async fn dispatch_download_page(
     Ok(())
 }
 
+/// 使用符号链接去重时，原文件被删除或移动后，其它视频来源中链接到该文件的分页会失效
+/// 检查同一视频在 source_model 之外的视频来源中的分页，重置失效分页的视频下载状态，使其在之后重新链接或下载
+async fn reset_dangling_links(
+    video_model: &video::Model,
+    source_model: &video_source::Model,
+    connection: &DatabaseConnection,
+) -> Result<()> {
+    if CONFIG.dedupe != DedupeMode::Symlink {
+        return Ok(());
+    }
+    let mut dangling = Vec::new();
+    for page_model in filter_video_pages(video_model.id, connection).await? {
+        if page_model.video_source_id == source_model.id {
+            continue;
+        }
+        let Some(path) = &page_model.path else {
+            continue;
+        };
+        // 符号链接本身存在，但指向的文件已经不存在
+        if fs::symlink_metadata(path).await.is_ok_and(|meta| meta.is_symlink()) && !fs::try_exists(path).await? {
+            dangling.push(page_model);
+        }
+    }
+    if dangling.is_empty() {
+        return Ok(());
+    }
+    let source_ids = dangling
+        .iter()
+        .map(|page_model| page_model.video_source_id)
+        .collect::<HashSet<_>>();
+    let txn = connection.begin().await?;
+    for page_model in dangling {
+        let mut status = PageStatus::from(page_model.download_status);
+        status.reset(1);
+        let mut page_active_model: page::ActiveModel = page_model.into();
+        page_active_model.download_status = Set(status.into());
+        page_active_model.update(&txn).await?;
+    }
+    for source_model in video_source::Entity::find()
+        .filter(video_source::Column::Id.is_in(source_ids))
+        .all(&txn)
+        .await?
+    {
+        let mut status = VideoStatus::from(source_model.download_status);
+        status.reset(4);
+        let mut source_active_model: video_source::ActiveModel = source_model.into();
+        source_active_model.download_status = Set(status.into());
+        source_active_model.update(&txn).await?;
+    }
+    txn.commit().await?;
+    info!(
+        "视频「{}」在其它视频来源中链接的文件已失效，将在之后重新下载",
+        &video_model.name
+    );
+    Ok(())
+}
+
+/// 获取同一视频在其它视频来源中已经下载完成的分页，键为分页的 pid
+async fn downloaded_pages(
+    video_model: &video::Model,
+    source_model: &video_source::Model,
+    connection: &DatabaseConnection,
+) -> Result<HashMap<i32, page::Model>> {
+    let mut downloaded = HashMap::new();
+    if CONFIG.dedupe == DedupeMode::None {
+        return Ok(downloaded);
+    }
+    for page_model in filter_video_pages(video_model.id, connection).await? {
+        if page_model.video_source_id == source_model.id
+            || downloaded.contains_key(&page_model.pid)
+            || !PageStatus::from(page_model.download_status).is_ok(1)
+        {
+            continue;
+        }
+        let Some(path) = &page_model.path else {
+            continue;
+        };
+        // 只复用真实存在的文件，避免链接到其它符号链接或已被删除的文件
+        if fs::symlink_metadata(path).await.is_ok_and(|meta| meta.is_file()) {
+            downloaded.insert(page_model.pid, page_model);
+        }
+    }
+    Ok(downloaded)
+}
+
 /// 下载某个分页，未发生风控且正常运行时返回 Ok(Page::ActiveModel)，其中 status 字段存储了新的下载状态，发生风控时返回 DownloadAbortError
+#[allow(clippy::too_many_arguments)]
 pub async fn download_page(
     bili_client: &BiliClient,
+    source: &SourceConfig,
+    album: &str,
     video_model: &video::Model,
+    source_model: &video_source::Model,
     page_model: page::Model,
+    downloaded: Option<&page::Model>,
     semaphore: &Semaphore,
     downloader: &Downloader,
     base_path: &Path,
@@ -362,11 +1571,16 @@ pub async fn download_page(
     let mut status = PageStatus::from(page_model.download_status);
     let seprate_status = status.should_run();
     let is_single_page = video_model.single_page.context("single_page is null")?;
-    let base_name = TEMPLATE.path_safe_render("page", &page_format_args(video_model, &page_model))?;
+    let base_name = TEMPLATE.path_safe_render(
+        &source.page_template(),
+        &page_format_args(video_model, source_model, &page_model),
+    )?;
+    let output = source.output();
+    let extension = output.format.extension();
     let (poster_path, video_path, nfo_path, danmaku_path, fanart_path, subtitle_path) = if is_single_page {
         (
             base_path.join(format!("{}-poster.jpg", &base_name)),
-            base_path.join(format!("{}.mp4", &base_name)),
+            base_path.join(format!("{}.{}", &base_name, extension)),
             base_path.join(format!("{}.nfo", &base_name)),
             base_path.join(format!("{}.zh-CN.default.ass", &base_name)),
             Some(base_path.join(format!("{}-fanart.jpg", &base_name))),
@@ -379,7 +1593,7 @@ pub async fn download_page(
                 .join(format!("{} - S01E{:0>2}-thumb.jpg", &base_name, page_model.pid)),
             base_path
                 .join("Season 1")
-                .join(format!("{} - S01E{:0>2}.mp4", &base_name, page_model.pid)),
+                .join(format!("{} - S01E{:0>2}.{}", &base_name, page_model.pid, extension)),
             base_path
                 .join("Season 1")
                 .join(format!("{} - S01E{:0>2}.nfo", &base_name, page_model.pid)),
@@ -405,43 +1619,137 @@ pub async fn download_page(
         cid: page_model.cid,
         duration: page_model.duration,
         dimension,
+        ep_id: video_model.ep_id,
         ..Default::default()
     };
-    let tasks: Vec<Pin<Box<dyn Future<Output = Result<()>> + Send>>> = vec![
+    // 视频文件已经处理完成时沿用记录的路径，其扩展名可能与当前的输出格式不同
+    let mut page_path = match (&page_model.path, seprate_status[1]) {
+        (Some(path), false) => PathBuf::from(path),
+        _ => video_path,
+    };
+    let current_quality = StreamQuality::from(&page_model);
+    // 已下载的文件格式不同时无法复用，重新下载已记录视频流的分页时（例如出现了更好的视频流）仅复用视频流更好的文件
+    let downloaded = downloaded
+        .and_then(|model| Some((Path::new(model.path.as_deref()?), StreamQuality::from(model))))
+        .filter(|(path, quality)| {
+            output.format.matches(path)
+                && (!current_quality.is_recorded() || quality.is_better_than(&current_quality, source.filter_option()))
+        });
+    // 本次下载的视频流，未下载时为 None
+    let mut quality = None;
+    let title = if is_single_page {
+        video_model.name.clone()
+    } else {
+        page_model.name.clone()
+    };
+    let date = video_model.pubtime.format("%Y-%m-%d").to_string();
+    // 字幕与 MKV 的章节都来自播放器接口，在两个子任务之间共享请求的结果
+    let player_info = OnceCell::new();
+    let video_task: Pin<Box<dyn Future<Output = Result<()>> + Send>> = if output.format == OutputFormat::Audio {
+        let tags = AudioTags {
+            cover: &poster_path,
+            title,
+            artist: video_model.upper_name.clone(),
+            album: album.to_owned(),
+            track: page_model.pid,
+            date,
+        };
+        let (page_path, quality, page_info) = (&mut page_path, &mut quality, &page_info);
+        Box::pin(async move {
+            // 音频的扩展名取决于下载的音频流，需要记录实际的文件路径
+            if let Some((path, stream_quality)) = fetch_page_audio(
+                seprate_status[1],
+                bili_client,
+                video_model,
+                source.filter_option(),
+                output,
+                downloader,
+                page_info,
+                downloaded,
+                page_path,
+                tags,
+            )
+            .await?
+            {
+                (*page_path, *quality) = (path, Some(stream_quality));
+            }
+            Ok(())
+        })
+    } else {
+        let mkv_sources = MkvSources {
+            cover: &poster_path,
+            danmaku: &danmaku_path,
+            subtitle: &subtitle_path,
+            title,
+            artist: video_model.upper_name.clone(),
+            date,
+        };
+        let (page_path, quality, page_info, player_info) = (&page_path, &mut quality, &page_info, &player_info);
+        Box::pin(async move {
+            *quality = fetch_page_video(
+                seprate_status[1],
+                bili_client,
+                video_model,
+                source.filter_option(),
+                output,
+                downloader,
+                page_info,
+                player_info,
+                downloaded,
+                page_path,
+                mkv_sources,
+            )
+            .await?;
+            Ok(())
+        })
+    };
+    let mut tasks: Vec<Pin<Box<dyn Future<Output = Result<()>> + Send>>> = vec![
         Box::pin(fetch_page_poster(
             seprate_status[0],
             video_model,
             &page_model,
             downloader,
-            poster_path,
+            poster_path.clone(),
             fanart_path,
         )),
-        Box::pin(fetch_page_video(
-            seprate_status[1],
-            bili_client,
+        Box::pin(generate_page_nfo(
+            seprate_status[2],
             video_model,
-            downloader,
-            &page_info,
-            &video_path,
+            source_model,
+            &page_model,
+            source.nfo_time_type(),
+            nfo_path,
         )),
-        Box::pin(generate_page_nfo(seprate_status[2], video_model, &page_model, nfo_path)),
         Box::pin(fetch_page_danmaku(
             seprate_status[3],
             bili_client,
             video_model,
+            source.danmaku_option(),
             &page_info,
-            danmaku_path,
+            danmaku_path.clone(),
         )),
         Box::pin(fetch_page_subtitle(
             seprate_status[4],
             bili_client,
             video_model,
             &page_info,
+            &player_info,
             &subtitle_path,
         )),
     ];
-    let tasks: FuturesOrdered<_> = tasks.into_iter().collect();
-    let results: Vec<Result<()>> = tasks.collect().await;
+    let results: Vec<Result<()>> = if output.format != OutputFormat::Mp4 {
+        // MKV 与音频文件需要嵌入封面、弹幕与字幕，等待其它子任务完成后再处理视频
+        let mut results: Vec<Result<()>> = tasks.into_iter().collect::<FuturesOrdered<_>>().collect().await;
+        results.insert(1, video_task.await);
+        results
+    } else {
+        tasks.insert(1, video_task);
+        tasks.into_iter().collect::<FuturesOrdered<_>>().collect().await
+    };
+    // 离开允许下载的时间段时不更新状态，中断的下载不计入失败次数
+    if is_download_paused(&results) {
+        bail!(DownloadPausedError());
+    }
     status.update_status(&results);
     results
         .iter()
@@ -462,12 +1770,30 @@ pub async fn download_page(
             bail!(DownloadAbortError());
         }
     }
+    if let (Some(_), Some(old_path)) = (&quality, &page_model.path) {
+        // 重新下载的文件扩展名可能发生变化（例如音频流由 m4a 升级为 flac），删除原有的文件
+        let old_path = Path::new(old_path);
+        if old_path != page_path && old_path.with_extension("") == page_path.with_extension("") {
+            unlink_file(old_path).await?;
+        }
+    }
     let mut page_active_model: page::ActiveModel = page_model.into();
     page_active_model.download_status = Set(status.into());
-    page_active_model.path = Set(Some(video_path.to_string_lossy().to_string()));
+    page_active_model.path = Set(Some(page_path.to_string_lossy().to_string()));
+    if let Some(quality) = quality {
+        quality.apply_to(&mut page_active_model);
+    }
     Ok(page_active_model)
 }
 
+/// 检查子任务是否因为离开允许下载的时间段而中断
+fn is_download_paused(results: &[Result<()>]) -> bool {
+    results.iter().any(|res| {
+        res.as_ref()
+            .is_err_and(|e| e.downcast_ref::<DownloadPausedError>().is_some())
+    })
+}
+
 pub async fn fetch_page_poster(
     should_run: bool,
     video_model: &video::Model,
@@ -497,64 +1823,217 @@ pub async fn fetch_page_poster(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn fetch_page_video(
     should_run: bool,
     bili_client: &BiliClient,
     video_model: &video::Model,
+    filter_option: &FilterOption,
+    output: &OutputOption,
     downloader: &Downloader,
     page_info: &PageInfo,
+    player_info: &OnceCell<PlayerInfo>,
+    downloaded: Option<(&Path, StreamQuality)>,
     page_path: &Path,
-) -> Result<()> {
+    mkv_sources: MkvSources<'_>,
+) -> Result<Option<StreamQuality>> {
     if !should_run {
-        return Ok(());
+        return Ok(None);
     }
-    let bili_video = Video::new(bili_client, video_model.bvid.clone());
+    if let Some((downloaded, quality)) = downloaded {
+        match link_file(downloaded, page_path, CONFIG.dedupe).await {
+            Ok(_) => return Ok(Some(quality)),
+            Err(e) => warn!("链接已下载的文件 {} 失败：{:#}，将重新下载", downloaded.display(), e),
+        }
+    }
+    let bili_video = Video::new(bili_client, video_model.bvid.clone())?;
     let streams = bili_video
         .get_page_analyzer(page_info)
         .await?
-        .best_stream(&CONFIG.filter_option)?;
-    match streams {
-        BestStream::Mixed(mix_stream) => downloader.fetch(mix_stream.url(), page_path).await,
+        .best_stream(filter_option)?;
+    let quality = streams.quality();
+    let (tmp_video_path, tmp_audio_path) = (
+        page_path.with_extension("tmp_video"),
+        page_path.with_extension("tmp_audio"),
+    );
+    let mkv = output.format == OutputFormat::Mkv;
+    // 输出 MKV 时，视频流先下载到临时文件中，之后与其它内容一起合并
+    let video_path = if mkv { tmp_video_path.as_path() } else { page_path };
+    // 记录下载的视频流对应的容器格式、是否为完整视频与是否包含音频（混合流无法提前得知），用于之后的校验
+    let (container, complete, audio) = match streams {
+        BestStream::Mixed(mix_stream) => {
+            downloader.multi_fetch(mix_stream.urls(), video_path).await?;
+            match mix_stream {
+                bilibili::Stream::Flv(_) => ("flv", true, None),
+                // 试看的视频仅包含一部分内容，不校验时长
+                bilibili::Stream::EpisodeTryMp4(_) => ("mp4", false, None),
+                _ => ("mp4", true, None),
+            }
+        }
         BestStream::VideoAudio {
             video: video_stream,
             audio: None,
-        } => downloader.fetch(video_stream.url(), page_path).await,
+        } => {
+            downloader.multi_fetch(video_stream.urls(), video_path).await?;
+            ("mp4", true, Some(false))
+        }
         BestStream::VideoAudio {
             video: video_stream,
             audio: Some(audio_stream),
         } => {
-            let (tmp_video_path, tmp_audio_path) = (
-                page_path.with_extension("tmp_video"),
-                page_path.with_extension("tmp_audio"),
-            );
-            let res = async {
-                downloader.fetch(video_stream.url(), &tmp_video_path).await?;
-                downloader.fetch(audio_stream.url(), &tmp_audio_path).await?;
-                downloader.merge(&tmp_video_path, &tmp_audio_path, page_path).await
+            // 临时文件仅在下载完成后才会出现，已经存在的临时文件无需重新下载
+            for (stream, tmp_path) in [(video_stream, &tmp_video_path), (audio_stream, &tmp_audio_path)] {
+                if !fs::try_exists(tmp_path).await? {
+                    downloader.multi_fetch(stream.urls(), tmp_path).await?;
+                }
+            }
+            if !mkv {
+                unlink_file(page_path).await?;
+                let res = downloader.merge(&tmp_video_path, &tmp_audio_path, page_path).await;
+                let _ = fs::remove_file(&tmp_video_path).await;
+                let _ = fs::remove_file(&tmp_audio_path).await;
+                res?;
             }
-            .await;
-            let _ = fs::remove_file(tmp_video_path).await;
-            let _ = fs::remove_file(tmp_audio_path).await;
-            res
+            ("mp4", true, Some(true))
+        }
+    };
+    let mut embedded_files = Vec::new();
+    let container = if mkv {
+        let chapters = async {
+            player_info
+                .get_or_try_init(|| bili_video.get_player_info(page_info))
+                .await?
+                .chapters()
+        }
+        .await
+        .unwrap_or_else(|e| {
+            warn!("获取视频「{}」的章节失败：{:#}，将不包含章节", &video_model.name, e);
+            Vec::new()
+        });
+        let input = mkv_sources.collect(chapters).await?;
+        let audio_path = (audio == Some(true)).then_some(tmp_audio_path.as_path());
+        unlink_file(page_path).await?;
+        let res = input.mux(&tmp_video_path, audio_path, page_path).await;
+        let _ = fs::remove_file(&tmp_video_path).await;
+        let _ = fs::remove_file(&tmp_audio_path).await;
+        res?;
+        embedded_files.extend(input.embedded_files().cloned());
+        "matroska"
+    } else {
+        container
+    };
+    verify_page_file(
+        page_path,
+        &Expectation {
+            container,
+            duration: complete.then_some(page_info.duration),
+            video: true,
+            dimension: page_info.dimension.as_ref(),
+            audio,
+        },
+    )
+    .await?;
+    if !output.sidecar {
+        for file in embedded_files {
+            fs::remove_file(file).await?;
+        }
+    }
+    Ok(Some(quality))
+}
+
+/// 仅下载分页的音频，返回实际写入的文件路径与下载的音频流
+#[allow(clippy::too_many_arguments)]
+pub async fn fetch_page_audio(
+    should_run: bool,
+    bili_client: &BiliClient,
+    video_model: &video::Model,
+    filter_option: &FilterOption,
+    output: &OutputOption,
+    downloader: &Downloader,
+    page_info: &PageInfo,
+    downloaded: Option<(&Path, StreamQuality)>,
+    page_path: &Path,
+    tags: AudioTags<'_>,
+) -> Result<Option<(PathBuf, StreamQuality)>> {
+    if !should_run {
+        return Ok(None);
+    }
+    if let Some((downloaded, quality)) = downloaded {
+        // 复用的文件可能是 m4a 或 flac，保持扩展名一致
+        let target = page_path.with_extension(downloaded.extension().unwrap_or_default());
+        match link_file(downloaded, &target, CONFIG.dedupe).await {
+            Ok(_) => return Ok(Some((target, quality))),
+            Err(e) => warn!("链接已下载的文件 {} 失败：{:#}，将重新下载", downloaded.display(), e),
         }
     }
+    let bili_video = Video::new(bili_client, video_model.bvid.clone())?;
+    let stream = bili_video
+        .get_page_analyzer(page_info)
+        .await?
+        .best_audio_stream(filter_option)?;
+    // Hi-Res 无损音频为 FLAC 编码，其余的音频流均可以直接放入 m4a 中
+    let (page_path, container) = match &stream {
+        bilibili::Stream::DashAudio {
+            quality: AudioQuality::QualityHiRES,
+            ..
+        } => (page_path.with_extension("flac"), "flac"),
+        _ => (page_path.with_extension("m4a"), "mp4"),
+    };
+    let tmp_audio_path = page_path.with_extension("tmp_audio");
+    // 临时文件仅在下载完成后才会出现，已经存在的临时文件无需重新下载
+    if !fs::try_exists(&tmp_audio_path).await? {
+        downloader.multi_fetch(stream.urls(), &tmp_audio_path).await?;
+    }
+    unlink_file(&page_path).await?;
+    let res = tags.write(&tmp_audio_path, &page_path).await;
+    let _ = fs::remove_file(&tmp_audio_path).await;
+    res?;
+    verify_page_file(
+        &page_path,
+        &Expectation {
+            container,
+            // 试看的视频仅包含一部分内容，不校验时长
+            duration: (!matches!(stream, bilibili::Stream::EpisodeTryMp4(_))).then_some(page_info.duration),
+            video: false,
+            dimension: None,
+            audio: Some(true),
+        },
+    )
+    .await?;
+    if !output.sidecar && fs::try_exists(tags.cover).await? {
+        fs::remove_file(tags.cover).await?;
+    }
+    Ok(Some((page_path, stream.quality())))
+}
+
+/// 开启校验时校验下载完成的文件，删除校验失败的文件，避免被当作已下载的文件复用
+async fn verify_page_file(page_path: &Path, expectation: &Expectation<'_>) -> Result<()> {
+    if !CONFIG.verify.enabled {
+        return Ok(());
+    }
+    if let Err(e) = verify_video(page_path, expectation, CONFIG.verify.duration_tolerance).await {
+        let _ = fs::remove_file(page_path).await;
+        return Err(e.context(format!("verify {} failed", page_path.display())));
+    }
+    Ok(())
 }
 
 pub async fn fetch_page_danmaku(
     should_run: bool,
     bili_client: &BiliClient,
     video_model: &video::Model,
+    danmaku_option: &DanmakuOption,
     page_info: &PageInfo,
     danmaku_path: PathBuf,
 ) -> Result<()> {
-    if !should_run {
+    if !should_run || !danmaku_option.enabled {
         return Ok(());
     }
-    let bili_video = Video::new(bili_client, video_model.bvid.clone());
+    let bili_video = Video::new(bili_client, video_model.bvid.clone())?;
     bili_video
         .get_danmaku_writer(page_info)
         .await?
-        .write(danmaku_path)
+        .write(danmaku_path, danmaku_option)
         .await
 }
 
@@ -563,13 +2042,17 @@ pub async fn fetch_page_subtitle(
     bili_client: &BiliClient,
     video_model: &video::Model,
     page_info: &PageInfo,
+    player_info: &OnceCell<PlayerInfo>,
     subtitle_path: &Path,
 ) -> Result<()> {
     if !should_run {
         return Ok(());
     }
-    let bili_video = Video::new(bili_client, video_model.bvid.clone());
-    let subtitles = bili_video.get_subtitles(page_info).await?;
+    let bili_video = Video::new(bili_client, video_model.bvid.clone())?;
+    let player_info = player_info
+        .get_or_try_init(|| bili_video.get_player_info(page_info))
+        .await?;
+    let subtitles = bili_video.get_subtitles(player_info).await?;
     let tasks = subtitles
         .into_iter()
         .map(|subtitle| async move {
@@ -584,7 +2067,9 @@ pub async fn fetch_page_subtitle(
 pub async fn generate_page_nfo(
     should_run: bool,
     video_model: &video::Model,
+    source_model: &video_source::Model,
     page_model: &page::Model,
+    nfo_time_type: &NFOTimeType,
     nfo_path: PathBuf,
 ) -> Result<()> {
     if !should_run {
@@ -592,11 +2077,11 @@ pub async fn generate_page_nfo(
     }
     let single_page = video_model.single_page.context("single_page is null")?;
     let nfo_serializer = if single_page {
-        NFOSerializer(ModelWrapper::Video(video_model), NFOMode::MOVIE)
+        NFOSerializer(ModelWrapper::Video(video_model, source_model), NFOMode::MOVIE)
     } else {
         NFOSerializer(ModelWrapper::Page(page_model), NFOMode::EPOSODE)
     };
-    generate_nfo(nfo_serializer, nfo_path).await
+    generate_nfo(nfo_serializer, nfo_time_type, nfo_path).await
 }
 
 pub async fn fetch_video_poster(
@@ -626,38 +2111,47 @@ pub async fn fetch_upper_face(
     downloader.fetch(&video_model.upper_face, &upper_face_path).await
 }
 
-pub async fn generate_upper_nfo(should_run: bool, video_model: &video::Model, nfo_path: PathBuf) -> Result<()> {
+pub async fn generate_upper_nfo(
+    should_run: bool,
+    video_model: &video::Model,
+    source_model: &video_source::Model,
+    nfo_path: PathBuf,
+) -> Result<()> {
     if !should_run {
         return Ok(());
     }
-    let nfo_serializer = NFOSerializer(ModelWrapper::Video(video_model), NFOMode::UPPER);
-    generate_nfo(nfo_serializer, nfo_path).await
+    let nfo_serializer = NFOSerializer(ModelWrapper::Video(video_model, source_model), NFOMode::UPPER);
+    // UP 主信息由所有视频来源共享，因此使用全局的配置
+    generate_nfo(nfo_serializer, &CONFIG.nfo_time_type, nfo_path).await
 }
 
-pub async fn generate_video_nfo(should_run: bool, video_model: &video::Model, nfo_path: PathBuf) -> Result<()> {
+pub async fn generate_video_nfo(
+    should_run: bool,
+    video_model: &video::Model,
+    source_model: &video_source::Model,
+    nfo_time_type: &NFOTimeType,
+    nfo_path: PathBuf,
+) -> Result<()> {
     if !should_run {
         return Ok(());
     }
-    let nfo_serializer = NFOSerializer(ModelWrapper::Video(video_model), NFOMode::TVSHOW);
-    generate_nfo(nfo_serializer, nfo_path).await
+    let nfo_serializer = NFOSerializer(ModelWrapper::Video(video_model, source_model), NFOMode::TVSHOW);
+    generate_nfo(nfo_serializer, nfo_time_type, nfo_path).await
 }
 
 /// 创建 nfo_path 的父目录，然后写入 nfo 文件
-async fn generate_nfo(serializer: NFOSerializer<'_>, nfo_path: PathBuf) -> Result<()> {
+async fn generate_nfo(serializer: NFOSerializer<'_>, nfo_time_type: &NFOTimeType, nfo_path: PathBuf) -> Result<()> {
     if let Some(parent) = nfo_path.parent() {
         fs::create_dir_all(parent).await?;
     }
-    fs::write(
-        nfo_path,
-        serializer.generate_nfo(&CONFIG.nfo_time_type).await?.as_bytes(),
-    )
-    .await?;
+    fs::write(nfo_path, serializer.generate_nfo(nfo_time_type).await?.as_bytes()).await?;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use handlebars::handlebars_helper;
+    use sea_orm::ActiveValue::NotSet;
     use serde_json::json;
 
     use super::*;
@@ -726,4 +2220,202 @@ mod tests {
             "哈哈，你说得对，但是 Rust 是由 Mozilla 自主研发的一"
         );
     }
+
+    /// 创建仅存在于内存中的数据库与一个测试用的收藏夹
+    async fn test_video_list() -> Result<(DatabaseConnection, VideoListModelEnum)> {
+        use bili_sync_migration::{Migrator, MigratorTrait};
+
+        let connection = sea_orm::Database::connect("sqlite::memory:").await?;
+        Migrator::up(&connection, None).await?;
+        let video_list_model = VideoListModelEnum::from(favorite::Model {
+            id: 1,
+            f_id: 1,
+            name: "测试收藏夹".to_owned(),
+            path: String::new(),
+            created_at: String::new(),
+            latest_row_at: DateTime::default(),
+        });
+        Ok((connection, video_list_model))
+    }
+
+    #[tokio::test]
+    async fn test_refilter_videos() -> Result<()> {
+        let (connection, video_list_model) = test_video_list().await?;
+        let mut video_ids = Vec::new();
+        for (bvid, name) in [("BV1", "Rust 直播回放"), ("BV2", "Rust 入门")] {
+            let mut video_active_model = video::Model {
+                bvid: bvid.to_owned(),
+                name: name.to_owned(),
+                category: 2,
+                valid: true,
+                ..Default::default()
+            }
+            .into_active_model();
+            video_active_model.id = NotSet;
+            let video_model = video_active_model.insert(&connection).await?;
+            video_ids.push(video_model.id);
+        }
+        // 第一个视频在列表中被标题规则过滤，第二个视频在获取详情后被时长规则过滤
+        let filter: ContentFilter = toml::from_str(
+            r#"
+            title_exclude = ["直播回放"]
+            min_duration = 60
+            "#,
+        )?;
+        for (video_id, reason) in video_ids
+            .iter()
+            .zip(["标题匹配排除规则 直播回放", "时长 30 秒短于限制"])
+        {
+            let mut source_active_model = video_source::Model {
+                video_id: *video_id,
+                filtered_reason: Some(reason.to_owned()),
+                ..Default::default()
+            }
+            .into_active_model();
+            source_active_model.id = NotSet;
+            video_list_model.set_relation_id(&mut source_active_model);
+            source_active_model.insert(&connection).await?;
+        }
+        let detailed_source = video_source::Entity::find()
+            .filter(video_source::Column::VideoId.eq(video_ids[1]))
+            .one(&connection)
+            .await?
+            .context("source not found")?;
+        let mut page_active_model = page::Model {
+            video_source_id: detailed_source.id,
+            cid: 1,
+            pid: 1,
+            duration: 30,
+            ..Default::default()
+        }
+        .into_active_model();
+        page_active_model.id = NotSet;
+        page_active_model.insert(&connection).await?;
+        let filtered_reasons = || async {
+            Ok::<_, anyhow::Error>(
+                video_source::Entity::find()
+                    .all(&connection)
+                    .await?
+                    .into_iter()
+                    .map(|source_model| source_model.filtered_reason)
+                    .collect::<Vec<_>>(),
+            )
+        };
+        // 规则未变化时保持原有的过滤原因
+        refilter_videos(&video_list_model, &filter, &connection).await?;
+        assert!(filtered_reasons().await?.iter().all(Option::is_some));
+        // 移除时长限制后，仅第二个视频不再被过滤
+        let filter: ContentFilter = toml::from_str(r#"title_exclude = ["直播回放"]"#)?;
+        refilter_videos(&video_list_model, &filter, &connection).await?;
+        assert_eq!(
+            filtered_reasons().await?,
+            vec![Some("标题匹配排除规则 直播回放".to_owned()), None]
+        );
+        // 移除所有规则后，所有视频都不再被过滤，之后会正常获取详情与下载
+        refilter_videos(&video_list_model, &ContentFilter::default(), &connection).await?;
+        assert!(filtered_reasons().await?.iter().all(Option::is_none));
+        assert_eq!(
+            filter_unfilled_videos(video_list_model.filter_expr(), &connection)
+                .await?
+                .len(),
+            1
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retention_episodes() -> Result<()> {
+        let (connection, video_list_model) = test_video_list().await?;
+        let dir = std::env::temp_dir().join(format!("bili-sync-retention-{}", std::process::id()));
+        let season = dir.join("Season 1");
+        std::fs::create_dir_all(&season)?;
+        std::fs::write(dir.join("tvshow.nfo"), b"")?;
+        // 两集番剧共用同一个文件夹，每集的文件约 0.6 MB，合计超出 1 MB 的保留大小
+        for ep in 1..=2 {
+            let mut video_active_model = video::Model {
+                bvid: format!("BV{}", ep),
+                name: format!("番剧 第{}集", ep),
+                category: 2,
+                valid: true,
+                single_page: Some(false),
+                ep_id: Some(100 + ep),
+                ..Default::default()
+            }
+            .into_active_model();
+            video_active_model.id = NotSet;
+            let video_model = video_active_model.insert(&connection).await?;
+            let mut source_active_model = video_source::Model {
+                video_id: video_model.id,
+                path: dir.to_string_lossy().to_string(),
+                favtime: chrono::NaiveDate::from_ymd_opt(2024, 1, ep as u32).unwrap().into(),
+                ..Default::default()
+            }
+            .into_active_model();
+            source_active_model.id = NotSet;
+            video_list_model.set_relation_id(&mut source_active_model);
+            let source_model = source_active_model.insert(&connection).await?;
+            let page_path = season.join(format!("番剧 - S01E0{}.mp4", ep));
+            std::fs::write(&page_path, vec![0u8; 600 * 1024])?;
+            let mut page_active_model = page::Model {
+                video_source_id: source_model.id,
+                cid: ep,
+                pid: 1,
+                path: Some(page_path.to_string_lossy().to_string()),
+                ..Default::default()
+            }
+            .into_active_model();
+            page_active_model.id = NotSet;
+            page_active_model.insert(&connection).await?;
+        }
+        let source = SourceConfig {
+            path: dir.clone(),
+            options: SourceOption {
+                retention: Some(RetentionPolicy {
+                    max_size: Some(1),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        };
+        apply_retention(&video_list_model, &source, &connection).await?;
+        // 仅删除较早的一集，共用的文件夹与最新一集的文件保持不变
+        assert!(!season.join("番剧 - S01E01.mp4").exists());
+        assert!(season.join("番剧 - S01E02.mp4").exists());
+        assert!(dir.join("tvshow.nfo").exists());
+        let expired = video_source::Entity::find()
+            .all(&connection)
+            .await?
+            .into_iter()
+            .map(|source_model| source_model.expired_at.is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(expired, vec![true, false]);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_partial_files() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("bili-sync-upgrade-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        // 旧视频流已经下载完成的文件与下载新视频流时中断遗留的文件
+        let stale = [
+            "视频.mp4.part",
+            "视频.tmp_video",
+            "视频.tmp_audio.part",
+            "视频.tmp_audio.part.validator",
+            "视频.tmp_video.part1",
+        ];
+        for file in stale.iter().chain(&["视频.mp4", "视频.nfo"]) {
+            std::fs::write(dir.join(file), b"stale")?;
+        }
+        remove_partial_files(&dir.join("视频.mp4")).await?;
+        // 升级时不会复用旧视频流的部分数据，已下载的文件保持不变，直到新的视频流下载完成
+        for file in stale {
+            assert!(!dir.join(file).exists(), "{} should be removed", file);
+        }
+        assert!(dir.join("视频.mp4").exists());
+        assert!(dir.join("视频.nfo").exists());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 }
@@ -2,9 +2,12 @@
 
 pub mod prelude;
 
+pub mod adhoc;
+pub mod bangumi;
 pub mod collection;
 pub mod favorite;
 pub mod page;
 pub mod submission;
 pub mod video;
+pub mod video_source;
 pub mod watch_later;
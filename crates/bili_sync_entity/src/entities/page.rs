@@ -7,7 +7,7 @@ use sea_orm::entity::prelude::*;
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
-    pub video_id: i32,
+    pub video_source_id: i32,
     pub cid: i64,
     pub pid: i32,
     pub name: String,
@@ -17,22 +17,27 @@ pub struct Model {
     pub path: Option<String>,
     pub image: Option<String>,
     pub download_status: u32,
+    pub previous_path: Option<String>,
     pub created_at: String,
+    pub video_quality: Option<i32>,
+    pub video_codec: Option<String>,
+    pub audio_quality: Option<i32>,
+    pub quality_checked_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(
-        belongs_to = "super::video::Entity",
-        from = "Column::VideoId",
-        to = "super::video::Column::Id"
+        belongs_to = "super::video_source::Entity",
+        from = "Column::VideoSourceId",
+        to = "super::video_source::Column::Id"
     )]
-    Video,
+    VideoSource,
 }
 
-impl Related<super::video::Entity> for Entity {
+impl Related<super::video_source::Entity> for Entity {
     fn to() -> RelationDef {
-        Relation::Video.def()
+        Relation::VideoSource.def()
     }
 }
 
@@ -12,6 +12,7 @@ pub struct Model {
     pub path: String,
     pub created_at: String,
     pub latest_row_at: DateTime,
+    pub from_following: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
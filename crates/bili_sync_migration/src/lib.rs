@@ -5,6 +5,16 @@ mod m20240505_130850_add_collection;
 mod m20240709_130914_watch_later;
 mod m20240724_161008_submission;
 mod m20250122_062926_add_latest_row_at;
+mod m20261018_120000_add_bangumi;
+mod m20261018_130000_add_submission_from_following;
+mod m20261018_140000_add_adhoc;
+mod m20261018_150000_add_video_removal;
+mod m20261018_160000_add_video_pages_checked_at;
+mod m20261018_170000_add_metadata_refresh;
+mod m20261018_180000_add_video_filtered_reason;
+mod m20261018_190000_add_video_expired_at;
+mod m20261018_200000_add_video_source;
+mod m20261018_210000_add_page_stream;
 
 pub struct Migrator;
 
@@ -17,6 +27,16 @@ impl MigratorTrait for Migrator {
             Box::new(m20240709_130914_watch_later::Migration),
             Box::new(m20240724_161008_submission::Migration),
             Box::new(m20250122_062926_add_latest_row_at::Migration),
+            Box::new(m20261018_120000_add_bangumi::Migration),
+            Box::new(m20261018_130000_add_submission_from_following::Migration),
+            Box::new(m20261018_140000_add_adhoc::Migration),
+            Box::new(m20261018_150000_add_video_removal::Migration),
+            Box::new(m20261018_160000_add_video_pages_checked_at::Migration),
+            Box::new(m20261018_170000_add_metadata_refresh::Migration),
+            Box::new(m20261018_180000_add_video_filtered_reason::Migration),
+            Box::new(m20261018_190000_add_video_expired_at::Migration),
+            Box::new(m20261018_200000_add_video_source::Migration),
+            Box::new(m20261018_210000_add_page_stream::Migration),
         ]
     }
 }
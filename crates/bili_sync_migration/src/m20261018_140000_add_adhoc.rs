@@ -0,0 +1,85 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::schema::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        // 通过命令行单独下载的视频统一记录在 adhoc 下，与稍后再看相同，该表仅有一行
+        manager
+            .create_table(
+                Table::create()
+                    .table(Adhoc::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Adhoc::Id)
+                            .unsigned()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Adhoc::Path).string().not_null())
+                    .col(
+                        ColumnDef::new(Adhoc::CreatedAt)
+                            .timestamp()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .col(timestamp(Adhoc::LatestRowAt).default("1970-01-01 00:00:00"))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_index(Index::drop().table(Video::Table).name("idx_video_unique").to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Video::Table)
+                    .add_column(ColumnDef::new(Video::AdhocId).unsigned().null())
+                    .to_owned(),
+            )
+            .await?;
+        db.execute_unprepared("CREATE UNIQUE INDEX `idx_video_unique` ON `video` (ifnull(`collection_id`, -1), ifnull(`favorite_id`, -1), ifnull(`watch_later_id`, -1), ifnull(`submission_id`, -1), ifnull(`bangumi_id`, -1), ifnull(`adhoc_id`, -1), `bvid`)")
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        manager
+            .drop_index(Index::drop().table(Video::Table).name("idx_video_unique").to_owned())
+            .await?;
+        db.execute_unprepared("DELETE FROM video WHERE adhoc_id IS NOT NULL")
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Video::Table)
+                    .drop_column(Video::AdhocId)
+                    .to_owned(),
+            )
+            .await?;
+        db.execute_unprepared("CREATE UNIQUE INDEX `idx_video_unique` ON `video` (ifnull(`collection_id`, -1), ifnull(`favorite_id`, -1), ifnull(`watch_later_id`, -1), ifnull(`submission_id`, -1), ifnull(`bangumi_id`, -1), `bvid`)")
+            .await?;
+        manager.drop_table(Table::drop().table(Adhoc::Table).to_owned()).await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Adhoc {
+    Table,
+    Id,
+    Path,
+    CreatedAt,
+    LatestRowAt,
+}
+
+#[derive(DeriveIden)]
+enum Video {
+    Table,
+    AdhocId,
+}
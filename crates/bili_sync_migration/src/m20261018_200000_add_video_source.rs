@@ -0,0 +1,268 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// 迁移到 video_source 之前，video 表中与视频来源相关的列
+const SOURCE_COLUMNS: [Video; 14] = [
+    Video::CollectionId,
+    Video::FavoriteId,
+    Video::WatchLaterId,
+    Video::SubmissionId,
+    Video::BangumiId,
+    Video::AdhocId,
+    Video::Path,
+    Video::Favtime,
+    Video::DownloadStatus,
+    Video::RemovedAt,
+    Video::ArchivePath,
+    Video::PreviousPath,
+    Video::FilteredReason,
+    Video::ExpiredAt,
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        // 同一视频在不同视频来源中仅保留一行 video 记录，视频来源相关的路径与状态移动到 video_source 中
+        manager
+            .create_table(
+                Table::create()
+                    .table(VideoSource::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(VideoSource::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(VideoSource::VideoId).integer().not_null())
+                    .col(ColumnDef::new(VideoSource::SourceType).integer().not_null())
+                    .col(ColumnDef::new(VideoSource::SourceId).integer().not_null())
+                    .col(ColumnDef::new(VideoSource::Path).string().not_null())
+                    .col(ColumnDef::new(VideoSource::Favtime).timestamp().not_null())
+                    .col(ColumnDef::new(VideoSource::DownloadStatus).unsigned().not_null())
+                    .col(ColumnDef::new(VideoSource::RemovedAt).timestamp().null())
+                    .col(ColumnDef::new(VideoSource::ArchivePath).string().null())
+                    .col(ColumnDef::new(VideoSource::PreviousPath).string().null())
+                    .col(ColumnDef::new(VideoSource::FilteredReason).string().null())
+                    .col(ColumnDef::new(VideoSource::ExpiredAt).timestamp().null())
+                    .col(
+                        ColumnDef::new(VideoSource::CreatedAt)
+                            .timestamp()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .table(VideoSource::Table)
+                    .name("idx_video_source_unique")
+                    .col(VideoSource::SourceType)
+                    .col(VideoSource::SourceId)
+                    .col(VideoSource::VideoId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .table(VideoSource::Table)
+                    .name("idx_video_source_video_id")
+                    .col(VideoSource::VideoId)
+                    .to_owned(),
+            )
+            .await?;
+        // 沿用原有 video 的 id 作为关联的 id，这样 page 中记录的 id 无需修改
+        // 相同 bvid 的视频合并到已经获取过详情的一行上，没有时使用 id 最小的一行
+        db.execute_unprepared(
+            "INSERT INTO video_source (id, video_id, source_type, source_id, path, favtime, download_status, removed_at, archive_path, previous_path, filtered_reason, expired_at, created_at)
+            SELECT v.id,
+                (SELECT c.id FROM video c WHERE c.bvid = v.bvid ORDER BY c.single_page IS NULL, c.id LIMIT 1),
+                CASE
+                    WHEN v.favorite_id IS NOT NULL THEN 1
+                    WHEN v.collection_id IS NOT NULL THEN 2
+                    WHEN v.watch_later_id IS NOT NULL THEN 3
+                    WHEN v.submission_id IS NOT NULL THEN 4
+                    WHEN v.bangumi_id IS NOT NULL THEN 5
+                    ELSE 6
+                END,
+                COALESCE(v.favorite_id, v.collection_id, v.watch_later_id, v.submission_id, v.bangumi_id, v.adhoc_id),
+                v.path, v.favtime, v.download_status, v.removed_at, v.archive_path, v.previous_path, v.filtered_reason, v.expired_at, v.created_at
+            FROM video v
+            WHERE COALESCE(v.favorite_id, v.collection_id, v.watch_later_id, v.submission_id, v.bangumi_id, v.adhoc_id) IS NOT NULL",
+        )
+        .await?;
+        db.execute_unprepared("DELETE FROM video WHERE id NOT IN (SELECT video_id FROM video_source)")
+            .await?;
+        manager
+            .drop_index(Index::drop().table(Video::Table).name("idx_video_unique").to_owned())
+            .await?;
+        for column in SOURCE_COLUMNS {
+            manager
+                .alter_table(Table::alter().table(Video::Table).drop_column(column).to_owned())
+                .await?;
+        }
+        manager
+            .create_index(
+                Index::create()
+                    .table(Video::Table)
+                    .name("idx_video_bvid")
+                    .col(Video::Bvid)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        // 分页的下载路径与状态同样属于某个视频来源，改为关联到 video_source
+        manager
+            .drop_index(
+                Index::drop()
+                    .table(Page::Table)
+                    .name("idx_page_video_id_pid")
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Page::Table)
+                    .rename_column(Page::VideoId, Page::VideoSourceId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .table(Page::Table)
+                    .name("idx_page_video_source_id_pid")
+                    .col(Page::VideoSourceId)
+                    .col(Page::Pid)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        manager
+            .drop_index(
+                Index::drop()
+                    .table(Page::Table)
+                    .name("idx_page_video_source_id_pid")
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Page::Table)
+                    .rename_column(Page::VideoSourceId, Page::VideoId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .table(Page::Table)
+                    .name("idx_page_video_id_pid")
+                    .col(Page::VideoId)
+                    .col(Page::Pid)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_index(Index::drop().table(Video::Table).name("idx_video_bvid").to_owned())
+            .await?;
+        for column in SOURCE_COLUMNS {
+            let mut column_def = ColumnDef::new(column);
+            match column {
+                Video::Path => column_def.string().not_null().default(""),
+                Video::Favtime => column_def.timestamp().not_null().default("1970-01-01 00:00:00"),
+                Video::DownloadStatus => column_def.unsigned().not_null().default(0),
+                Video::ArchivePath | Video::PreviousPath | Video::FilteredReason => column_def.string().null(),
+                Video::RemovedAt | Video::ExpiredAt => column_def.timestamp().null(),
+                _ => column_def.unsigned().null(),
+            };
+            manager
+                .alter_table(Table::alter().table(Video::Table).add_column(column_def).to_owned())
+                .await?;
+        }
+        // 每个关联重新拆分为一行 video，id 与关联的 id 保持一致，先将原有的行移到负数 id 上避免冲突
+        db.execute_unprepared("UPDATE video SET id = -id").await?;
+        db.execute_unprepared(
+            "INSERT INTO video (id, upper_id, upper_name, upper_face, name, category, bvid, intro, cover, ctime, pubtime, valid, tags, single_page, ep_id, pages_checked_at, metadata_checked_at, created_at,
+                favorite_id, collection_id, watch_later_id, submission_id, bangumi_id, adhoc_id,
+                path, favtime, download_status, removed_at, archive_path, previous_path, filtered_reason, expired_at)
+            SELECT s.id, v.upper_id, v.upper_name, v.upper_face, v.name, v.category, v.bvid, v.intro, v.cover, v.ctime, v.pubtime, v.valid, v.tags, v.single_page, v.ep_id, v.pages_checked_at, v.metadata_checked_at, s.created_at,
+                CASE WHEN s.source_type = 1 THEN s.source_id END,
+                CASE WHEN s.source_type = 2 THEN s.source_id END,
+                CASE WHEN s.source_type = 3 THEN s.source_id END,
+                CASE WHEN s.source_type = 4 THEN s.source_id END,
+                CASE WHEN s.source_type = 5 THEN s.source_id END,
+                CASE WHEN s.source_type = 6 THEN s.source_id END,
+                s.path, s.favtime, s.download_status, s.removed_at, s.archive_path, s.previous_path, s.filtered_reason, s.expired_at
+            FROM video_source s JOIN video v ON v.id = -s.video_id",
+        )
+        .await?;
+        db.execute_unprepared("DELETE FROM video WHERE id < 0").await?;
+        db.execute_unprepared("CREATE UNIQUE INDEX `idx_video_unique` ON `video` (ifnull(`collection_id`, -1), ifnull(`favorite_id`, -1), ifnull(`watch_later_id`, -1), ifnull(`submission_id`, -1), ifnull(`bangumi_id`, -1), ifnull(`adhoc_id`, -1), `bvid`)")
+            .await?;
+        manager
+            .drop_table(Table::drop().table(VideoSource::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum VideoSource {
+    Table,
+    Id,
+    VideoId,
+    SourceType,
+    SourceId,
+    Path,
+    Favtime,
+    DownloadStatus,
+    RemovedAt,
+    ArchivePath,
+    PreviousPath,
+    FilteredReason,
+    ExpiredAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum Video {
+    Table,
+    Bvid,
+    CollectionId,
+    FavoriteId,
+    WatchLaterId,
+    SubmissionId,
+    BangumiId,
+    AdhocId,
+    Path,
+    Favtime,
+    DownloadStatus,
+    RemovedAt,
+    ArchivePath,
+    PreviousPath,
+    FilteredReason,
+    ExpiredAt,
+}
+
+#[derive(DeriveIden)]
+enum Page {
+    Table,
+    VideoId,
+    VideoSourceId,
+    Pid,
+}
@@ -59,6 +59,7 @@ export default defineConfig({
 						link: "/collection",
 					},
 					{ text: "获取投稿信息", link: "/submission" },
+					{ text: "获取番剧信息", link: "/bangumi" },
 				],
 			},
 		],